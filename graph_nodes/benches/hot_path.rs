@@ -0,0 +1,93 @@
+// Times the per-sample loops of Volume, BaseMix and Rate against the
+// iterator and indexing loops they used before running over contiguous
+// slices. Run with `cargo bench`.
+//
+// Three runs on a single core Xeon VM, in ns/sample:
+//
+//   volume iter    3.5 - 4.2    volume slices  2.9 - 3.0
+//   mix index      6.2 - 10.3   mix slices     0.3 - 0.5
+//   rate index     2.7 - 4.8    rate slices    4.5 - 6.8
+//
+// Only mixing is clearly faster. Volume's time goes to converting samples to
+// and from f32 for its gain, which costs about the same however the loop
+// runs. Rate is slower since it copies whole frames through Frame.
+
+extern crate graph_nodes;
+extern crate graph_utils;
+
+use std::time::{Duration, Instant};
+
+use graph_nodes::{Rate, Volume};
+use graph_utils::{BaseMix, Node, RingBuffer, Sample};
+
+const SAMPLES: usize = 4096;
+const ROUNDS: usize = 2000;
+
+// A ring holding SAMPLES samples, half of them wrapped around its end.
+fn wrapped_ring() -> RingBuffer {
+    let mut ring = RingBuffer::new();
+    ring.max_length = SAMPLES + 1;
+    ring.write_from(SAMPLES / 2, &vec!(0; SAMPLES / 2));
+    ring.read_slice(SAMPLES / 2);
+    ring
+}
+
+// Nanoseconds per sample of update over rounds of SAMPLES samples. Filling
+// the inputs and draining the output aren't timed.
+fn time<F: FnMut(&mut [RingBuffer], &mut [RingBuffer])>(name: &str, inputs: usize, mut update: F) {
+    let samples = (0..SAMPLES).map(|i| (i * 7) as i16).collect::<Vec<i16>>();
+    let mut inputs = (0..inputs).map(|_| wrapped_ring()).collect::<Vec<RingBuffer>>();
+    let mut outputs = vec!(wrapped_ring());
+    let mut elapsed = Duration::from_secs(0);
+    for _ in 0..ROUNDS {
+        for input in inputs.iter_mut() {
+            input.write_from(SAMPLES, &samples);
+        }
+        let start = Instant::now();
+        update(&mut inputs, &mut outputs);
+        elapsed += start.elapsed();
+        let written = outputs[0].len();
+        outputs[0].read_slice(written);
+    }
+    let nanos = elapsed.as_secs() as f64 * 1e9 + elapsed.subsec_nanos() as f64;
+    println!("{:<16} {:>8.3} ns/sample", name, nanos / (ROUNDS * SAMPLES) as f64);
+}
+
+fn main() {
+    time("volume iter", 1, |inputs, outputs| {
+        let avail = inputs[0].len();
+        for (i, o) in inputs[0].read_slice(avail).iter().zip(outputs[0].write_slice(avail).iter_mut()) {
+            *o = (*i as i32 * 3 / 4) as i16;
+        }
+    });
+    let mut volume = Volume::new((3, 4));
+    time("volume slices", 1, |inputs, outputs| volume.update(inputs, outputs));
+
+    let mut accum = vec!(0i16; SAMPLES);
+    time("mix index", 3, |inputs, outputs| {
+        let avail = SAMPLES;
+        for i in 0..avail {
+            accum[i] = 0;
+        }
+        for input in inputs.iter_mut() {
+            let slice = input.read_slice(avail);
+            for i in 0..avail {
+                accum[i] = accum[i].mix(slice[i]);
+            }
+        }
+        outputs[0].write_from(avail, &accum);
+    });
+    let mut mix = BaseMix::new();
+    time("mix slices", 3, |inputs, outputs| mix.update(inputs, outputs));
+
+    time("rate index", 1, |inputs, outputs| {
+        let (num, denom) = (SAMPLES / 2, SAMPLES / 2 * 441 / 480);
+        let slice = inputs[0].read_slice(num * 2);
+        let mut out_slice = outputs[0].write_slice(denom * 2);
+        for (index, o) in out_slice.iter_mut().enumerate() {
+            *o = slice[index / 2 * num / denom * 2 + index % 2];
+        }
+    });
+    let mut rate = Rate::new(48000, 44100);
+    time("rate slices", 1, |inputs, outputs| rate.update(inputs, outputs));
+}
@@ -1,4 +1,4 @@
-use graph_utils::{Callback, CallbackInner, RingBuffer};
+use graph_utils::{Callback, CallbackInner, RingBuffer, StreamFormat};
 
 pub struct MonoToStereo(Callback);
 
@@ -6,6 +6,18 @@ impl CallbackInner for MonoToStereo {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn input_format(&self) -> StreamFormat {
+        StreamFormat::any().with_channels(1)
+    }
+
+    fn output_format(&self, input: StreamFormat) -> StreamFormat {
+        input.with_channels(2)
+    }
+
+    fn kind(&self) -> &'static str {
+        "mono_to_stereo"
+    }
 }
 
 impl MonoToStereo {
@@ -1,58 +1,137 @@
-use std::rc::Rc;
-use std::cell::Cell;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
-use graph_utils::{Callback, CallbackInner, RingBuffer};
+use graph_utils::{Callback, CallbackInner, Frame, Node, NodeInfo, ParamEvent, ParamRW, ParamRead, Ramp, RingBuffer, Sample, StreamFormat, SMOOTHING, port_inputs, scale};
 
 #[derive(Clone)]
-pub struct DuckState(Rc<Cell<bool>>);
+pub struct DuckState(Arc<AtomicBool>);
 
-pub struct Duck(Callback);
+// The duck, the peak level, in i16 sample units, that ducks, and the format
+// of the frames it reads.
+pub struct Duck(Callback, ParamRW, Arc<Mutex<StreamFormat>>);
 
-pub struct Ducked(Callback);
+// The ducked node, the volume it ducks to and the format its envelope is
+// timed in.
+pub struct Ducked(Callback, ParamRW, Arc<Mutex<StreamFormat>>);
 
 impl DuckState {
     pub fn new() -> DuckState {
-        DuckState(Rc::new(Cell::new(false)))
+        DuckState(Arc::new(AtomicBool::new(false)))
     }
 }
 
+// How long Ducked takes to move to its volume when a duck starts, and back
+// when it ends.
+const DUCK_ATTACK_MS: u64 = 10;
+const DUCK_RELEASE_MS: u64 = 250;
+
+// How long a duck holds for after the last frame peaking.
+const DUCK_HOLD_MS: usize = 1000;
+
+// Frames a duck holds for in format, at 48000 Hz until the graph says.
+fn hold_frames(format: StreamFormat) -> usize {
+    format.rate.unwrap_or(48000) * DUCK_HOLD_MS / 1000
+}
+
+// Count in held the frames since the last one with a sample over peak.
+// Returns whether any frame peaked.
+fn hold_peaks(frames: &Frame, peak: f32, held: &mut usize) -> bool {
+    let mut peaked = false;
+    for frame in frames.frames() {
+        if frame.iter().any(|&sample| sample as f32 > peak) {
+            peaked = true;
+            *held = 0;
+        }
+        else {
+            *held += 1;
+        }
+    }
+    peaked
+}
+
+// A volume written as (num, denom) as a ParamRW.
+fn ratio_param((num, denom): (i32, i32)) -> ParamRW {
+    ParamRW::new(num as f32 / denom as f32)
+}
+
+// Ducked's gain with the envelope from 0, not ducked, to 1, ducked to
+// volume.
+fn duck_gain(volume: f32, envelope: f32) -> f32 {
+    // Land on volume exactly once the envelope finishes.
+    if envelope == 1.0 {volume} else {1.0 + (volume - 1.0) * envelope}
+}
+
 impl CallbackInner for Duck {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        let changed = {
+            let mut current = self.2.lock().unwrap();
+            let changed = *current != format;
+            *current = format;
+            changed
+        };
+        if changed {
+            Node::reset(&mut self.0);
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "duck"
+    }
 }
 
 impl Duck {
     pub fn new(peak: i16, state: DuckState) -> Box<Duck> {
-        let mut active = false;
-        let mut last_peak = Instant::now();
-        let mut samples = 0;
-        Box::new(Duck(Callback::new(Box::new(move |input, output| {
-            let avail = input.len();
-            let slice = input.read_slice(avail);
-            samples += slice.len() / 2;
-            for i in slice.iter() {
-                if *i > peak {
+        Duck::with_threshold(ParamRW::new(peak as f32), state)
+    }
+
+    // Holds the duck for DUCK_HOLD_MS after the last frame over threshold.
+    // It reads as many channels as the graph hands it, 2 until it knows.
+    pub fn with_threshold(threshold: ParamRW, state: DuckState) -> Box<Duck> {
+        let read_threshold = threshold.clone();
+        let format = Arc::new(Mutex::new(StreamFormat::any()));
+        let factory_format = format.clone();
+        // A reset duck lets go of the ducked nodes until it peaks again.
+        Box::new(Duck(Callback::resettable(Box::new(move || {
+            state.0.store(false, Ordering::Relaxed);
+            let format = *factory_format.lock().unwrap();
+            let channels = format.channels.unwrap_or(2);
+            let hold = hold_frames(format);
+            let mut frames = Frame::new(channels);
+            let mut active = false;
+            let mut held = 0;
+            let state = state.clone();
+            let mut threshold = read_threshold.clone_read(0);
+            Box::new(move |input, output| {
+                threshold.update();
+                let avail = input.frames(channels);
+                frames.read_from(input, avail);
+                if hold_peaks(&frames, threshold.get(), &mut held) {
                     active = true;
-                    state.0.set(true);
-                    // last_peak = Instant::now();
-                    samples = 0;
+                    state.0.store(true, Ordering::Relaxed);
                 }
-            }
-            if !active {
-                for o in output.write_slice(avail).iter_mut() {
-                    *o = 0;
+                if !active {
+                    for o in output.write_slice(frames.samples().len()).iter_mut() {
+                        *o = 0;
+                    }
                 }
-            }
-            else {
-                output.write_from_read_slice(slice.len(), &slice);
-                if active && samples > 48000 {
-                    active = false;
-                    state.0.set(false);
+                else {
+                    frames.write_to(output);
+                    if held > hold {
+                        active = false;
+                        state.0.store(false, Ordering::Relaxed);
+                    }
                 }
-            }
-        }))))
+            })
+        })), threshold, format))
+    }
+
+    pub fn threshold(&self) -> &ParamRW {
+        &self.1
     }
 }
 
@@ -60,22 +139,196 @@ impl CallbackInner for Ducked {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        let changed = {
+            let mut current = self.2.lock().unwrap();
+            let changed = *current != format;
+            *current = format;
+            changed
+        };
+        if changed {
+            Node::reset(&mut self.0);
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "ducked"
+    }
 }
 
 impl Ducked {
     pub fn new(states: Vec<DuckState>, volume: (i32, i32)) -> Box<Ducked> {
-        Box::new(Ducked(Callback::new(Box::new(move |input, output| {
-            if !input.active {return;}
-            let avail = input.len();
-            let slice = input.read_slice(avail);
-            if states.iter().any(|state| state.0.get()) {
-                for (i, o) in slice.iter().zip(output.write_slice(avail).iter_mut()) {
-                    *o = (*i as i32 * volume.0 / volume.1) as i16;
+        Ducked::with_volume(states, ratio_param(volume))
+    }
+
+    // Ramps its input's gain down to volume while any of states is ducking
+    // and back up after.
+    pub fn with_volume(states: Vec<DuckState>, volume: ParamRW) -> Box<Ducked> {
+        let read_volume = volume.clone();
+        let format = Arc::new(Mutex::new(StreamFormat::any()));
+        let factory_format = format.clone();
+        Box::new(Ducked(Callback::resettable(Box::new(move || {
+            let states = states.clone();
+            let mut volume = read_volume.clone_read(SMOOTHING);
+            // 0 passes input through, 1 scales it by volume.
+            let mut envelope = ParamRW::new(0.0).clone_read(0).with_format(*factory_format.lock().unwrap());
+            // where the last ramp scheduled heads, as envelope's target only
+            // moves once it starts
+            let mut envelope_target = 0.0;
+            Box::new(move |input, output| {
+                if !input.active {return;}
+                volume.update();
+                let ducking = states.iter().any(|state| state.0.load(Ordering::Relaxed));
+                let target = if ducking {1.0} else {0.0};
+                if envelope_target != target {
+                    envelope_target = target;
+                    let ms = if ducking {DUCK_ATTACK_MS} else {DUCK_RELEASE_MS};
+                    envelope.schedule(ParamEvent::new(Ramp::Linear, target, Duration::from_millis(ms)));
                 }
+                let avail = input.len();
+                let slice = input.read_slice(avail);
+                slice.zip_runs(&mut output.write_slice(avail), |input, output| {
+                    if volume.is_steady_for(input.len()) && envelope.is_steady_for(input.len()) {
+                        let gain = duck_gain(volume.get(), envelope.get());
+                        volume.skip(input.len());
+                        envelope.skip(input.len());
+                        scale(input, output, gain);
+                    }
+                    else {
+                        for (i, o) in input.iter().zip(output.iter_mut()) {
+                            let gain = duck_gain(volume.next(), envelope.next());
+                            *o = i16::from_f32(i.to_f32() * gain);
+                        }
+                    }
+                });
+            })
+        })), volume, format))
+    }
+
+    pub fn volume(&self) -> &ParamRW {
+        &self.1
+    }
+}
+
+const SIDECHAIN_DUCKED_INPUTS: &'static [&'static str] = &["main", "sidechain"];
+
+// Ducks its main input while its sidechain input peaks, instead of sharing a
+// DuckState with a Duck node elsewhere in the graph.
+pub struct SidechainDucked {
+    peak: ParamRead,
+    // the sidechain's frames, as many channels as the graph hands it
+    sidechain: Frame,
+    // frames since the sidechain last peaked
+    held: usize,
+    // frames held for, from the rate the graph hands it
+    hold: usize,
+    state: DuckState,
+    ducked: Box<Ducked>,
+}
+
+impl SidechainDucked {
+    pub fn new(peak: i16, volume: (i32, i32)) -> Box<SidechainDucked> {
+        SidechainDucked::with_params(ParamRW::new(peak as f32), ratio_param(volume))
+    }
+
+    // Ducks to volume while the sidechain peaks over threshold.
+    pub fn with_params(threshold: ParamRW, volume: ParamRW) -> Box<SidechainDucked> {
+        let state = DuckState::new();
+        Box::new(SidechainDucked {
+            peak: threshold.clone_read(0),
+            sidechain: Frame::new(2),
+            held: 0,
+            hold: hold_frames(StreamFormat::any()),
+            state: state.clone(),
+            ducked: Ducked::with_volume(vec!(state), volume),
+        })
+    }
+}
+
+impl Node for SidechainDucked {
+    fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+        self.peak.update();
+        let peak = self.peak.get();
+        let channels = self.sidechain.channels();
+        let mut heard = 0;
+        for input in port_inputs(inputs, 1).iter_mut() {
+            let avail = input.frames(channels);
+            heard += avail;
+            self.sidechain.read_from(input, avail);
+            if hold_peaks(&self.sidechain, peak, &mut self.held) {
+                self.state.0.store(true, Ordering::Relaxed);
             }
-            else {
-                output.write_from_read_slice(avail, &slice);
-            }
-        }))))
+        }
+        if heard == 0 {
+            // A stalled or disconnected sidechain is quiet, so the main
+            // input's frames count toward the hold.
+            self.held += port_inputs(inputs, 0).iter().map(|input| input.frames(channels)).max().unwrap_or(0);
+        }
+        if self.state.0.load(Ordering::Relaxed) && self.held > self.hold {
+            self.state.0.store(false, Ordering::Relaxed);
+        }
+        self.ducked.update(port_inputs(inputs, 0), outputs);
+    }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        let channels = format.channels.unwrap_or(2);
+        if self.sidechain.channels() != channels {
+            self.sidechain = Frame::new(channels);
+        }
+        self.hold = hold_frames(format);
+        Node::set_input_format(&mut *self.ducked, format);
+    }
+
+    fn reset(&mut self) {
+        self.held = 0;
+        self.state.0.store(false, Ordering::Relaxed);
+        Node::reset(&mut *self.ducked);
+    }
+
+    fn flush(&mut self) {
+        Node::flush(&mut *self.ducked);
+    }
+
+    fn input_ports(&self) -> &[&'static str] {
+        SIDECHAIN_DUCKED_INPUTS
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("sidechain_ducked")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::Ordering;
+
+    use graph_utils::{Node, RingBuffer, StreamFormat};
+
+    use super::SidechainDucked;
+
+    fn ring(to_port: usize, frames: usize, level: i16) -> RingBuffer {
+        let mut ring = RingBuffer::new();
+        ring.to_port = to_port;
+        ring.write_from(frames * 2, &vec!(level; frames * 2));
+        ring
+    }
+
+    #[test]
+    fn it_lets_go_when_the_sidechain_stalls() {
+        let mut ducked = SidechainDucked::new(5500, (1, 5));
+        // 1000 Hz holds the duck for 1000 frames.
+        ducked.set_input_format(StreamFormat::new(1000, 2));
+        let mut outputs = vec!(RingBuffer::new());
+        ducked.update(&mut [ring(0, 10, 1000), ring(1, 10, 6000)], &mut outputs);
+        assert!(ducked.state.0.load(Ordering::Relaxed));
+
+        // Nothing comes in on the sidechain while the main input plays on.
+        for _ in 0..100 {
+            ducked.update(&mut [ring(0, 10, 1000), ring(1, 0, 0)], &mut outputs);
+        }
+        assert!(ducked.state.0.load(Ordering::Relaxed));
+        ducked.update(&mut [ring(0, 10, 1000)], &mut outputs);
+        assert!(!ducked.state.0.load(Ordering::Relaxed));
     }
 }
@@ -1,46 +1,70 @@
-let fade_in = || {
-    let mut buffer = Vec::new();
-    let mut playing = false;
-    let mut last = Instant::now();
-    let mut volume = 0;
-    let mut timeout = 9600;
-    let mut samples = 0;
-    Box::new(Callback::new(Box::new(move |input, output| {
-        let avail = input.len();
-        input.read_into(avail, &mut buffer);
-        if avail > 0 {
-            // let now = Instant::now();
-            // let since = now.duration_since(last);
-            samples = 0;
-            if !playing {
-                playing = true;
-                volume = 0;
-            }
-
-            for i in 0..avail {
-                buffer[i] = (buffer[i] as i32 * max(volume, 0) / 48000) as i16;
-                if i % 4 == 0 {
-                    volume = min(volume + 8, 48000);
-                }
-            }
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-            // last = now;
-        }
-        else if playing {
-            // let now = Instant::now();
-            // let since = now.duration_since(last);
-            // if since.as_secs() > 0 || since.subsec_nanos() > (timeout * 1e9) as u32 {
-            samples += 48;
-            if samples > timeout {
-                playing = false;
-                volume = 0;
-            }
-        }
-        if !playing {
-            for i in 0..avail {
-                buffer[i] = 0;
-            }
+use graph_utils::{Callback, CallbackInner, Clock, Node, ParamEvent, ParamRW, Ramp, StreamFormat};
+
+// How long the input has to go without samples before the next ones fade in
+// again.
+const QUIET_MS: u64 = 200;
+
+// Fades its input in each time it starts after going quiet, like a stream
+// that was paused. The fade is timed in the rate and channels the graph hands
+// it.
+pub struct FadeIn(Callback, Arc<Mutex<StreamFormat>>);
+
+impl CallbackInner for FadeIn {
+    fn get_callback(&mut self) -> &mut Callback {
+        &mut self.0
+    }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        let changed = {
+            let mut current = self.1.lock().unwrap();
+            let changed = *current != format;
+            *current = format;
+            changed
+        };
+        if changed {
+            Node::reset(&mut self.0);
         }
-        output.write_from(avail, &buffer);
-    })))
-};
+    }
+
+    fn kind(&self) -> &'static str {
+        "fade_in"
+    }
+}
+
+impl FadeIn {
+    pub fn new(fade: Duration) -> Box<FadeIn> {
+        FadeIn::with_clock(fade, Clock::system())
+    }
+
+    // Goes quiet on the given clock instead of the system's.
+    pub fn with_clock(fade: Duration, clock: Clock) -> Box<FadeIn> {
+        let format = Arc::new(Mutex::new(StreamFormat::any()));
+        let factory_format = format.clone();
+        Box::new(FadeIn(Callback::resettable(Box::new(move || {
+            let format = *factory_format.lock().unwrap();
+            let mut gain = ParamRW::new(0.0).clone_read(0).with_format(format);
+            let mut playing = false;
+            let mut last_heard = clock.now();
+            let clock = clock.clone();
+            Box::new(move |input, output| {
+                let avail = input.len();
+                if avail == 0 {
+                    if clock.now().duration_since(last_heard) > Duration::from_millis(QUIET_MS) {
+                        playing = false;
+                    }
+                    return;
+                }
+                last_heard = clock.now();
+                if !playing {
+                    playing = true;
+                    gain.schedule(ParamEvent::new(Ramp::Step, 0.0, Duration::new(0, 0)));
+                    gain.schedule(ParamEvent::new(Ramp::Linear, 1.0, fade));
+                }
+                input.read_slice(avail).zip_runs(&mut output.write_slice(avail), |input, output| gain.apply(input, output));
+            })
+        })), format))
+    }
+}
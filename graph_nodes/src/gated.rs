@@ -44,6 +44,10 @@ impl CallbackInner for Gated {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn kind(&self) -> &'static str {
+        "gated"
+    }
 }
 
 impl Gated {
@@ -91,6 +95,10 @@ impl CallbackInner for Switched {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn kind(&self) -> &'static str {
+        "switched"
+    }
 }
 
 impl Switched {
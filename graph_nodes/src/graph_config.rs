@@ -0,0 +1,898 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::time::Duration;
+
+use rustc_serialize::json::{Json, ParserError};
+
+use graph_utils::{Graph, GraphError, EdgeState, Node, BaseMix, Clock, OverflowPolicy, ParamRW};
+
+use activation::*;
+use channels::*;
+use duck::*;
+use fade_in::*;
+use gated::*;
+use io_graph::*;
+use rate::*;
+use volume::*;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Json(ParserError),
+    // node name, field
+    MissingField(String, &'static str),
+    InvalidField(String, &'static str),
+    // node name, kind
+    UnknownKind(String, String),
+    UnknownNode(String),
+    // node name, port
+    UnknownPort(String, String),
+    DuplicateNode(String),
+    Graph(GraphError),
+}
+
+pub struct NodeConfig {
+    pub name: String,
+    pub kind: String,
+    pub to: Vec<String>,
+    // Nodes reading this node's output an update late, closing a loop.
+    pub feedback_to: Vec<String>,
+    json: Json,
+}
+
+pub struct GraphConfig {
+    pub nodes: Vec<NodeConfig>,
+}
+
+pub struct GraphHandles {
+    pub ids: BTreeMap<String, usize>,
+    pub gates: BTreeMap<String, GateState>,
+    pub switches: BTreeMap<String, SwitchState>,
+    pub ducks: BTreeMap<String, DuckState>,
+    pub io_buffers: BTreeMap<String, IoNodeBuffer>,
+    // Values that can be set while the graph runs, keyed "node.field".
+    pub params: BTreeMap<String, ParamRW>,
+}
+
+pub type NodeFactory = Box<Fn(&NodeConfig) -> Result<Box<Node>, ConfigError>>;
+
+pub struct GraphLoader {
+    activation_controller: ActivationController,
+    clock: Clock,
+    factories: BTreeMap<String, NodeFactory>,
+}
+
+impl NodeConfig {
+    fn from_json(json: Json) -> Result<NodeConfig, ConfigError> {
+        let name = match json.find("name").and_then(|name| name.as_string()) {
+            Some(name) => String::from(name),
+            None => return Err(ConfigError::MissingField(String::from(""), "name")),
+        };
+        let kind = match json.find("kind").and_then(|kind| kind.as_string()) {
+            Some(kind) => String::from(kind),
+            None => return Err(ConfigError::MissingField(name, "kind")),
+        };
+        let mut node = NodeConfig {
+            name: name,
+            kind: kind,
+            to: Vec::new(),
+            feedback_to: Vec::new(),
+            json: json,
+        };
+        if node.get("to").is_some() {
+            node.to = try!(node.get_str_list("to"));
+        }
+        if node.get("feedback_to").is_some() {
+            node.feedback_to = try!(node.get_str_list("feedback_to"));
+        }
+        Ok(node)
+    }
+
+    pub fn get(&self, field: &str) -> Option<&Json> {
+        self.json.find(field)
+    }
+
+    // Whether two configs build the same node, whatever their edges.
+    pub fn same_node(&self, other: &NodeConfig) -> bool {
+        let without_edges = |json: &Json| {
+            let mut json = json.clone();
+            if let Json::Object(ref mut fields) = json {
+                fields.remove("to");
+                fields.remove("feedback_to");
+            }
+            json
+        };
+        self.kind == other.kind && without_edges(&self.json) == without_edges(&other.json)
+    }
+
+    fn get_field(&self, field: &'static str) -> Result<&Json, ConfigError> {
+        match self.get(field) {
+            Some(value) => Ok(value),
+            None => Err(ConfigError::MissingField(self.name.clone(), field)),
+        }
+    }
+
+    fn invalid(&self, field: &'static str) -> ConfigError {
+        ConfigError::InvalidField(self.name.clone(), field)
+    }
+
+    pub fn get_u64(&self, field: &'static str) -> Result<u64, ConfigError> {
+        let value = try!(self.get_field(field));
+        value.as_u64().ok_or_else(|| self.invalid(field))
+    }
+
+    // An optional field, like a fade's ms, with the value to use without it.
+    pub fn get_u64_or(&self, field: &'static str, default: u64) -> Result<u64, ConfigError> {
+        match self.get(field) {
+            Some(value) => value.as_u64().ok_or_else(|| self.invalid(field)),
+            None => Ok(default),
+        }
+    }
+
+    // An optional tcp_port to listen on, like an io node's.
+    pub fn get_tcp_port(&self) -> Result<Option<u16>, ConfigError> {
+        match self.get("tcp_port") {
+            Some(value) => match value.as_u64() {
+                Some(port) if port >= 1 && port <= u16::max_value() as u64 => Ok(Some(port as u16)),
+                _ => Err(self.invalid("tcp_port")),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_i64(&self, field: &'static str) -> Result<i64, ConfigError> {
+        let value = try!(self.get_field(field));
+        value.as_i64().ok_or_else(|| self.invalid(field))
+    }
+
+    // A level in i16 sample units, like a duck's peak.
+    pub fn get_i16(&self, field: &'static str) -> Result<i16, ConfigError> {
+        let value = try!(self.get_i64(field));
+        if value < i16::min_value() as i64 || value > i16::max_value() as i64 {
+            return Err(self.invalid(field));
+        }
+        Ok(value as i16)
+    }
+
+    pub fn get_str(&self, field: &'static str) -> Result<&str, ConfigError> {
+        let value = try!(self.get_field(field));
+        value.as_string().ok_or_else(|| self.invalid(field))
+    }
+
+    pub fn get_str_list(&self, field: &'static str) -> Result<Vec<String>, ConfigError> {
+        let value = try!(self.get_field(field));
+        let list = try!(value.as_array().ok_or_else(|| self.invalid(field)));
+        let mut strings = Vec::new();
+        for item in list.iter() {
+            match item.as_string() {
+                Some(s) => strings.push(String::from(s)),
+                None => return Err(self.invalid(field)),
+            }
+        }
+        Ok(strings)
+    }
+
+    // A volume written as [num, denom].
+    pub fn get_ratio(&self, field: &'static str) -> Result<(i32, i32), ConfigError> {
+        let value = try!(self.get_field(field));
+        match value.as_array() {
+            Some(pair) if pair.len() == 2 => {
+                match (pair[0].as_i64(), pair[1].as_i64()) {
+                    (Some(num), Some(denom)) if denom != 0 => Ok((num as i32, denom as i32)),
+                    _ => Err(self.invalid(field)),
+                }
+            },
+            _ => Err(self.invalid(field)),
+        }
+    }
+}
+
+impl GraphConfig {
+    pub fn from_json(json: Json) -> Result<GraphConfig, ConfigError> {
+        let mut nodes = Vec::new();
+        match json.find("nodes").and_then(|nodes| nodes.as_array()) {
+            Some(node_list) => {
+                for node in node_list.iter() {
+                    nodes.push(try!(NodeConfig::from_json(node.clone())));
+                }
+            },
+            None => return Err(ConfigError::MissingField(String::from(""), "nodes")),
+        }
+        Ok(GraphConfig {
+            nodes: nodes,
+        })
+    }
+
+    pub fn from_str(s: &str) -> Result<GraphConfig, ConfigError> {
+        match Json::from_str(s) {
+            Ok(json) => GraphConfig::from_json(json),
+            Err(err) => Err(ConfigError::Json(err)),
+        }
+    }
+
+    pub fn from_reader(reader: &mut io::Read) -> Result<GraphConfig, ConfigError> {
+        match Json::from_reader(reader) {
+            Ok(json) => GraphConfig::from_json(json),
+            Err(err) => Err(ConfigError::Json(err)),
+        }
+    }
+}
+
+impl GraphHandles {
+    pub fn new() -> GraphHandles {
+        GraphHandles {
+            ids: BTreeMap::new(),
+            gates: BTreeMap::new(),
+            switches: BTreeMap::new(),
+            ducks: BTreeMap::new(),
+            io_buffers: BTreeMap::new(),
+            params: BTreeMap::new(),
+        }
+    }
+
+    // A new param for a node's field, replacing one of a node built before
+    // with the same name.
+    fn param(&mut self, node: &str, field: &str, value: f32) -> ParamRW {
+        let param = ParamRW::new(value);
+        self.params.insert(format!("{}.{}", node, field), param.clone());
+        param
+    }
+
+    fn remove_params(&mut self, node: &str) {
+        let prefix = format!("{}.", node);
+        let keys = self.params.keys().filter(|key| key.starts_with(&prefix)).cloned().collect::<Vec<String>>();
+        for key in keys.iter() {
+            self.params.remove(key);
+        }
+    }
+}
+
+fn ratio_value((num, denom): (i32, i32)) -> f32 {
+    num as f32 / denom as f32
+}
+
+impl GraphLoader {
+    pub fn new(activation_controller: ActivationController) -> GraphLoader {
+        GraphLoader {
+            activation_controller: activation_controller,
+            clock: Clock::system(),
+            factories: BTreeMap::new(),
+        }
+    }
+
+    // The clock built nodes read time from, like a simulated one for an
+    // offline render.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    // Adds a node kind the loader doesn't know how to build on its own, like
+    // alsa devices.
+    pub fn add_kind(&mut self, kind: &str, factory: NodeFactory) {
+        self.factories.insert(String::from(kind), factory);
+    }
+
+    pub fn build(&self, config: &NodeConfig, handles: &mut GraphHandles) -> Result<Box<Node>, ConfigError> {
+        let node: Box<Node> = match config.kind.as_str() {
+            "base_mix" => Box::new(BaseMix::new()),
+            "mono_to_stereo" => MonoToStereo::new(),
+            "rate" => {
+                let input_rate = try!(config.get_u64("input_rate"));
+                let output_rate = try!(config.get_u64("output_rate"));
+                Rate::new(input_rate as usize, output_rate as usize)
+            },
+            "fade_in" => FadeIn::with_clock(Duration::from_millis(try!(config.get_u64_or("ms", 250))), self.clock.clone()),
+            "volume" => {
+                let volume = try!(config.get_ratio("volume"));
+                Volume::with_param(handles.param(&config.name, "volume", ratio_value(volume)))
+            },
+            "duck" => {
+                let peak = try!(config.get_i16("peak"));
+                let state = try!(config.get_str("state"));
+                let state = handles.ducks.entry(String::from(state)).or_insert_with(DuckState::new).clone();
+                let peak = handles.param(&config.name, "peak", peak as f32);
+                Duck::with_threshold(peak, state)
+            },
+            "ducked" => {
+                let volume = try!(config.get_ratio("volume"));
+                let mut states = Vec::new();
+                for state in try!(config.get_str_list("states")).into_iter() {
+                    states.push(handles.ducks.entry(state).or_insert_with(DuckState::new).clone());
+                }
+                Ducked::with_volume(states, handles.param(&config.name, "volume", ratio_value(volume)))
+            },
+            "sidechain_ducked" => {
+                let peak = try!(config.get_i16("peak"));
+                let volume = try!(config.get_ratio("volume"));
+                let peak = handles.param(&config.name, "peak", peak as f32);
+                let volume = handles.param(&config.name, "volume", ratio_value(volume));
+                SidechainDucked::with_params(peak, volume)
+            },
+            "gated" => {
+                let state = try!(config.get_str("state"));
+                let state = handles.gates.entry(String::from(state)).or_insert_with(GateState::new).clone();
+                Gated::new(state)
+            },
+            "switched" => {
+                let my_state = try!(config.get_u64("value"));
+                let state = try!(config.get_str("state"));
+                let state = handles.switches.entry(String::from(state)).or_insert_with(SwitchState::new).clone();
+                Switched::new(state, my_state as usize)
+            },
+            "io" => {
+                try!(config.get_tcp_port());
+                let io_buffer = IoNodeBuffer::with_clock(&config.name, self.activation_controller.clone(), self.clock.clone());
+                let capture = io_buffer.capture().unwrap();
+                handles.io_buffers.insert(config.name.clone(), io_buffer);
+                capture
+            },
+            kind => {
+                match self.factories.get(kind) {
+                    Some(factory) => try!(factory(config)),
+                    None => return Err(ConfigError::UnknownKind(config.name.clone(), String::from(kind))),
+                }
+            },
+        };
+        Ok(node)
+    }
+
+    pub fn load(&self, config: &GraphConfig, graph: &mut Graph) -> Result<GraphHandles, ConfigError> {
+        let mut handles = GraphHandles::new();
+        match self.load_into(config, graph, &mut handles) {
+            Ok(()) => Ok(handles),
+            Err(err) => {
+                // Don't leave half of the config running.
+                for &id in handles.ids.values() {
+                    let _ = graph.disconnect(id);
+                }
+                Err(err)
+            },
+        }
+    }
+
+    fn load_into(&self, config: &GraphConfig, graph: &mut Graph, handles: &mut GraphHandles) -> Result<(), ConfigError> {
+        for node_config in config.nodes.iter() {
+            if handles.ids.contains_key(&node_config.name) {
+                return Err(ConfigError::DuplicateNode(node_config.name.clone()));
+            }
+            let node = try!(self.build(node_config, handles));
+            let id = graph.connect(node, Default::default()).id();
+            handles.ids.insert(node_config.name.clone(), id);
+        }
+
+        for edge in try!(config_edges(config, &handles.ids)).iter() {
+            try!(add_config_edge(graph, edge));
+        }
+
+        Ok(())
+    }
+
+    // Apply config to a graph running old_config. Nodes whose config only
+    // changed in its edges keep running, so devices they hold stay open, and
+    // only new or changed nodes are built. Returns the names of the nodes
+    // built. Nothing changes if the config fails to build or connect.
+    pub fn reload(&self, old_config: &GraphConfig, config: &GraphConfig, graph: &mut Graph, handles: &mut GraphHandles) -> Result<Vec<String>, ConfigError> {
+        let survives = |node_config: &NodeConfig| {
+            old_config.nodes.iter().any(|old| old.name == node_config.name && old.same_node(node_config))
+        };
+
+        // Build everything first so a bad config leaves the graph alone.
+        let mut built_handles = GraphHandles {
+            ids: BTreeMap::new(),
+            gates: handles.gates.clone(),
+            switches: handles.switches.clone(),
+            ducks: handles.ducks.clone(),
+            io_buffers: BTreeMap::new(),
+            params: handles.params.clone(),
+        };
+        let mut built = Vec::new();
+        for node_config in config.nodes.iter() {
+            if config.nodes.iter().filter(|other| other.name == node_config.name).count() > 1 {
+                return Err(ConfigError::DuplicateNode(node_config.name.clone()));
+            }
+            if !survives(node_config) {
+                built.push((node_config.name.clone(), try!(self.build(node_config, &mut built_handles))));
+            }
+        }
+        let removed = old_config.nodes.iter()
+        .filter(|node_config| !config.nodes.iter().any(|new| new.name == node_config.name && survives(new)))
+        .collect::<Vec<&NodeConfig>>();
+        let mut ids = handles.ids.clone();
+        for node_config in removed.iter() {
+            ids.remove(&node_config.name);
+        }
+        for &(ref name, _) in built.iter() {
+            // Stand in for the ids the built nodes will get.
+            ids.insert(name.clone(), usize::max_value());
+        }
+        try!(config_edges(config, &ids));
+
+        // Connect the built nodes beside the ones they replace and rewire,
+        // undoing everything if an edge doesn't connect.
+        let mut names = Vec::new();
+        for (name, node) in built.into_iter() {
+            let id = graph.connect(node, Default::default()).id();
+            ids.insert(name.clone(), id);
+            names.push(name);
+        }
+        let removed_ids = removed.iter()
+        .filter_map(|node_config| handles.ids.get(&node_config.name).cloned())
+        .collect::<Vec<usize>>();
+        let edges = try!(config_edges(config, &ids));
+        let mut removed_edges = Vec::new();
+        let mut added_edges = Vec::new();
+        if let Err(err) = rewire(graph, &edges, &ids, &removed_ids, &mut removed_edges, &mut added_edges) {
+            for &index in added_edges.iter().rev() {
+                let _ = remove_config_edge(graph, &edges[index]);
+            }
+            for state in removed_edges.iter() {
+                let _ = add_state_edge(graph, state);
+            }
+            for name in names.iter() {
+                let _ = graph.disconnect(ids[name]);
+            }
+            return Err(err);
+        }
+
+        for &id in removed_ids.iter() {
+            try!(graph.disconnect(id).map_err(ConfigError::Graph));
+        }
+        for node_config in removed.iter() {
+            handles.io_buffers.remove(&node_config.name);
+            if !config.nodes.iter().any(|new| new.name == node_config.name) {
+                built_handles.remove_params(&node_config.name);
+            }
+        }
+        handles.ids = ids;
+        handles.gates = built_handles.gates;
+        handles.switches = built_handles.switches;
+        handles.ducks = built_handles.ducks;
+        handles.params = built_handles.params;
+        handles.io_buffers.extend(built_handles.io_buffers.into_iter());
+
+        Ok(names)
+    }
+}
+
+// An edge named by a config, "name:port" in to connecting to a named input
+// port.
+struct ConfigEdge {
+    from: usize,
+    to: usize,
+    // the name of the node it connects to
+    to_name: String,
+    port: Option<String>,
+    feedback: bool,
+}
+
+impl ConfigEdge {
+    // The input port edge connects to, found among the ports the node has.
+    fn input_port(&self, graph: &Graph) -> Result<&'static str, ConfigError> {
+        let input_ports = graph.borrow(self.to).input_ports();
+        match self.port {
+            Some(ref port) => match input_ports.iter().find(|input_port| **input_port == port.as_str()) {
+                Some(&input_port) => Ok(input_port),
+                None => Err(ConfigError::UnknownPort(self.to_name.clone(), port.clone())),
+            },
+            None => Ok(input_ports[0]),
+        }
+    }
+
+    fn matches(&self, graph: &Graph, state: &EdgeState) -> bool {
+        let port = match self.port {
+            Some(ref port) => port.as_str(),
+            None => graph.borrow(self.to).input_ports()[0],
+        };
+        state.from == self.from && state.from_port == graph.borrow(self.from).output_ports()[0] &&
+            state.to == self.to && state.to_port == port && state.feedback == self.feedback
+    }
+}
+
+fn config_edges(config: &GraphConfig, ids: &BTreeMap<String, usize>) -> Result<Vec<ConfigEdge>, ConfigError> {
+    let mut edges = Vec::new();
+    for node_config in config.nodes.iter() {
+        let id = ids[&node_config.name];
+        for output in node_config.to.iter() {
+            let mut parts = output.splitn(2, ':');
+            let output_name = parts.next().unwrap();
+            let output_id = match ids.get(output_name) {
+                Some(&output_id) => output_id,
+                None => return Err(ConfigError::UnknownNode(String::from(output_name))),
+            };
+            edges.push(ConfigEdge {
+                from: id,
+                to: output_id,
+                to_name: String::from(output_name),
+                port: parts.next().map(String::from),
+                feedback: false,
+            });
+        }
+        for output_name in node_config.feedback_to.iter() {
+            let output_id = match ids.get(output_name) {
+                Some(&output_id) => output_id,
+                None => return Err(ConfigError::UnknownNode(output_name.clone())),
+            };
+            edges.push(ConfigEdge {
+                from: id,
+                to: output_id,
+                to_name: output_name.clone(),
+                port: None,
+                feedback: true,
+            });
+        }
+    }
+    Ok(edges)
+}
+
+fn add_config_edge(graph: &mut Graph, edge: &ConfigEdge) -> Result<(), ConfigError> {
+    let result = if edge.feedback {
+        graph.add_feedback_edge(edge.from, edge.to)
+    }
+    else {
+        let output_port = graph.borrow(edge.from).output_ports()[0];
+        let input_port = try!(edge.input_port(graph));
+        graph.add_port_edge(edge.from, output_port, edge.to, input_port)
+    };
+    result.map_err(ConfigError::Graph)
+}
+
+fn remove_config_edge(graph: &mut Graph, edge: &ConfigEdge) -> Result<(), ConfigError> {
+    let result = if edge.feedback {
+        graph.remove_feedback_edge(edge.from, edge.to)
+    }
+    else {
+        let output_port = graph.borrow(edge.from).output_ports()[0];
+        let input_port = try!(edge.input_port(graph));
+        graph.remove_port_edge(edge.from, output_port, edge.to, input_port)
+    };
+    result.map_err(ConfigError::Graph)
+}
+
+// Remove just the edge state describes, leaving others between its nodes.
+fn remove_state_edge(graph: &mut Graph, state: &EdgeState) -> Result<(), ConfigError> {
+    let result = if state.feedback {
+        graph.remove_feedback_edge(state.from, state.to)
+    }
+    else {
+        graph.remove_port_edge(state.from, state.from_port, state.to, state.to_port)
+    };
+    result.map_err(ConfigError::Graph)
+}
+
+// Put back an edge remove_state_edge took out.
+fn add_state_edge(graph: &mut Graph, state: &EdgeState) -> Result<(), ConfigError> {
+    let result = if state.feedback {
+        graph.add_feedback_edge(state.from, state.to)
+    }
+    else {
+        graph.add_port_edge(state.from, state.from_port, state.to, state.to_port)
+    };
+    try!(result.map_err(ConfigError::Graph));
+    if state.overflow != OverflowPolicy::default() {
+        try!(graph.set_overflow(state.from, state.to, state.overflow).map_err(ConfigError::Graph));
+    }
+    Ok(())
+}
+
+// Take out every edge of removed_ids and the edges between config nodes that
+// edges no longer has, then add the ones it gained. Edges go before any come
+// so the old wiring can't make a cycle with the new. Each change is noted in
+// removed and added, so a failure can be undone.
+fn rewire(graph: &mut Graph, edges: &[ConfigEdge], ids: &BTreeMap<String, usize>, removed_ids: &[usize], removed: &mut Vec<EdgeState>, added: &mut Vec<usize>) -> Result<(), ConfigError> {
+    let config_ids = ids.values().cloned().collect::<Vec<usize>>();
+    for state in graph.edge_states().into_iter() {
+        let obsolete = if removed_ids.contains(&state.from) || removed_ids.contains(&state.to) {
+            true
+        }
+        else {
+            config_ids.contains(&state.from) && config_ids.contains(&state.to) &&
+                !edges.iter().any(|edge| edge.matches(graph, &state))
+        };
+        if obsolete {
+            try!(remove_state_edge(graph, &state));
+            removed.push(state);
+        }
+    }
+    let states = graph.edge_states();
+    for (index, edge) in edges.iter().enumerate() {
+        if !states.iter().any(|state| edge.matches(graph, state)) {
+            try!(add_config_edge(graph, edge));
+            added.push(index);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use graph_utils::{Graph, BaseMix, Clock, OfflineRender, RenderSource, RenderSink, StreamFormat};
+
+    use activation::ActivationController;
+    use super::*;
+
+    #[test]
+    fn it_loads_a_graph() {
+        let config = GraphConfig::from_str(r#"{
+            "nodes": [
+                {"name": "chrome", "kind": "io", "to": ["chrome_gated", "content_duck"]},
+                {"name": "chrome_gated", "kind": "gated", "state": "chrome_device", "to": ["device_mix"]},
+                {"name": "content_duck", "kind": "ducked", "states": ["mic", "device"], "volume": [1, 5], "to": ["device_mix"]},
+                {"name": "mic_duck", "kind": "duck", "peak": 5500, "state": "mic", "to": ["device_mix"]},
+                {"name": "device_mix", "kind": "base_mix", "to": ["device_44", "mic_ducked:sidechain"]},
+                {"name": "device_44", "kind": "rate", "input_rate": 48000, "output_rate": 44100},
+                {"name": "mic_ducked", "kind": "sidechain_ducked", "peak": 5500, "volume": [1, 5]}
+            ]
+        }"#).unwrap();
+        let mut graph = Graph::new();
+        let loader = GraphLoader::new(ActivationController::new());
+        let handles = loader.load(&config, &mut graph).unwrap();
+
+        assert_eq!(handles.ids.len(), 7);
+        assert!(handles.gates.contains_key("chrome_device"));
+        assert_eq!(handles.ducks.len(), 2);
+        assert!(handles.io_buffers.contains_key("chrome"));
+        assert_eq!(handles.params["content_duck.volume"].get(), 0.2);
+        assert_eq!(handles.params["mic_duck.peak"].get(), 5500.0);
+        assert!(graph.borrow(handles.ids["device_mix"]).downcast_ref::<BaseMix>().is_some());
+        graph.update();
+    }
+
+    fn render_ducking() -> Vec<u8> {
+        let config = GraphConfig::from_str(r#"{
+            "nodes": [
+                {"name": "music", "kind": "source", "level": 1000, "to": ["content_duck"]},
+                {"name": "voice", "kind": "source", "level": 6000, "to": ["mic_duck"]},
+                {"name": "content_duck", "kind": "ducked", "states": ["mic"], "volume": [1, 5], "to": ["out"]},
+                {"name": "mic_duck", "kind": "duck", "peak": 5500, "state": "mic", "to": ["out"]},
+                {"name": "out", "kind": "sink"}
+            ]
+        }"#).unwrap();
+        let render = OfflineRender::new(Clock::simulated(), 48000, 2, 480);
+        let mut loader = GraphLoader::new(ActivationController::new());
+        loader.set_clock(render.clock().clone());
+        let block_samples = render.block_samples();
+        let format = render.format();
+        loader.add_kind("source", Box::new(move |config: &NodeConfig| {
+            let level = try!(config.get_i16("level"));
+            let node: Box<Node> = RenderSource::new((0..block_samples * 10).map(|_| level).collect(), block_samples, format);
+            Ok(node)
+        }));
+        loader.add_kind("sink", Box::new(move |_: &NodeConfig| {
+            let node: Box<Node> = RenderSink::new(format);
+            Ok(node)
+        }));
+
+        let mut graph = Graph::new();
+        let handles = loader.load(&config, &mut graph).unwrap();
+        render.render(&mut graph, 10);
+
+        let sink = graph.borrow(handles.ids["out"]).downcast_ref::<RenderSink>().unwrap();
+        assert_eq!(sink.samples().len(), 960 * 10);
+        // The music ramps down to its ducked volume instead of jumping.
+        assert!(sink.samples().iter().any(|&sample| sample > 6000 + 1000 / 5 && sample < 6000 + 1000));
+        assert_eq!(sink.samples()[960 * 10 - 1], 6000 + 1000 / 5);
+        let mut bytes = Vec::new();
+        render.write_wav(&mut bytes, sink).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn it_renders_offline() {
+        assert!(render_ducking() == render_ducking());
+    }
+
+    #[test]
+    fn it_reloads_changed_nodes() {
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "chrome", "kind": "io", "to": ["mix"]},
+            {"name": "mic", "kind": "volume", "volume": [1, 2], "to": ["mix"]},
+            {"name": "mix", "kind": "base_mix"}
+        ]}"#).unwrap();
+        let mut graph = Graph::new();
+        let loader = GraphLoader::new(ActivationController::new());
+        let mut handles = loader.load(&config, &mut graph).unwrap();
+        let mix_id = handles.ids["mix"];
+        let mic_id = handles.ids["mic"];
+
+        let new_config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "mic", "kind": "volume", "volume": [1, 3], "to": ["mix"]},
+            {"name": "mix", "kind": "base_mix", "to": ["out"]},
+            {"name": "out", "kind": "base_mix"}
+        ]}"#).unwrap();
+        let built = loader.reload(&config, &new_config, &mut graph, &mut handles).unwrap();
+        assert_eq!(built, vec!(String::from("mic"), String::from("out")));
+        assert_eq!(handles.ids["mix"], mix_id);
+        assert!(handles.ids["mic"] != mic_id);
+        assert!(!handles.ids.contains_key("chrome"));
+        assert!(!handles.io_buffers.contains_key("chrome"));
+        assert_eq!(handles.params["mic.volume"].get(), 1.0 / 3.0);
+
+        let mut edges = graph.edge_states().iter().map(|edge| (edge.from, edge.to)).collect::<Vec<(usize, usize)>>();
+        edges.sort();
+        assert_eq!(edges, vec!((mix_id, handles.ids["out"]), (handles.ids["mic"], mix_id)));
+        assert_eq!(graph.node_ids().len(), 3);
+
+        let bad_config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "mix", "kind": "base_mix", "to": ["nowhere"]}
+        ]}"#).unwrap();
+        match loader.reload(&new_config, &bad_config, &mut graph, &mut handles) {
+            Err(ConfigError::UnknownNode(name)) => assert_eq!(name, "nowhere"),
+            _ => panic!("expected an unknown node"),
+        }
+        assert_eq!(graph.node_ids().len(), 3);
+        graph.update();
+    }
+
+    #[test]
+    fn it_reloads_only_changed_edges() {
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "mic", "kind": "base_mix", "to": ["ducked", "ducked:sidechain"]},
+            {"name": "ducked", "kind": "sidechain_ducked", "peak": 5500, "volume": [1, 5], "feedback_to": ["mic"]}
+        ]}"#).unwrap();
+        let mut graph = Graph::new();
+        let loader = GraphLoader::new(ActivationController::new());
+        let mut handles = loader.load(&config, &mut graph).unwrap();
+        let mic_id = handles.ids["mic"];
+        let ducked_id = handles.ids["ducked"];
+
+        let new_config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "mic", "kind": "base_mix", "to": ["ducked"]},
+            {"name": "ducked", "kind": "sidechain_ducked", "peak": 5500, "volume": [1, 5], "feedback_to": ["mic"]}
+        ]}"#).unwrap();
+        assert_eq!(loader.reload(&config, &new_config, &mut graph, &mut handles).unwrap(), Vec::<String>::new());
+        let edges = graph.edge_states().iter().map(|edge| (edge.from, edge.to, edge.to_port, edge.feedback)).collect::<Vec<_>>();
+        assert_eq!(edges, vec!((mic_id, ducked_id, "main", false), (ducked_id, mic_id, "main", true)));
+    }
+
+    #[test]
+    fn it_leaves_the_graph_alone_when_a_reload_fails() {
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix", "to": ["mix"]},
+            {"name": "b", "kind": "volume", "volume": [1, 2], "to": ["mix"]},
+            {"name": "mix", "kind": "base_mix"}
+        ]}"#).unwrap();
+        let mut graph = Graph::new();
+        let loader = GraphLoader::new(ActivationController::new());
+        let mut handles = loader.load(&config, &mut graph).unwrap();
+        let snapshot = |graph: &Graph| {
+            let mut edges = graph.edge_states().iter()
+            .map(|edge| (edge.from, edge.from_port, edge.to, edge.to_port, edge.feedback))
+            .collect::<Vec<_>>();
+            edges.sort();
+            (graph.node_ids(), edges)
+        };
+        let before = snapshot(&graph);
+        let ids = handles.ids.clone();
+
+        // b is rebuilt and a's edge dropped before the new edges close a
+        // cycle.
+        let cyclic_config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix"},
+            {"name": "b", "kind": "volume", "volume": [1, 3], "to": ["mix"]},
+            {"name": "mix", "kind": "base_mix", "to": ["b"]}
+        ]}"#).unwrap();
+        match loader.reload(&config, &cyclic_config, &mut graph, &mut handles) {
+            Err(ConfigError::Graph(GraphError::Cycle(_, _))) => {},
+            _ => panic!("expected a cycle"),
+        }
+        assert_eq!(snapshot(&graph), before);
+        assert_eq!(handles.ids, ids);
+        assert_eq!(handles.params["b.volume"].get(), 0.5);
+
+        let mismatched_config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix"},
+            {"name": "b", "kind": "volume", "volume": [1, 2], "to": ["mix"]},
+            {"name": "mix", "kind": "base_mix", "to": ["down"]},
+            {"name": "down", "kind": "rate", "input_rate": 48000, "output_rate": 44100, "to": ["again"]},
+            {"name": "again", "kind": "rate", "input_rate": 48000, "output_rate": 44100}
+        ]}"#).unwrap();
+        match loader.reload(&config, &mismatched_config, &mut graph, &mut handles) {
+            Err(ConfigError::Graph(GraphError::FormatMismatch(_, _, _, _))) => {},
+            _ => panic!("expected mismatched rates"),
+        }
+        assert_eq!(snapshot(&graph), before);
+        assert_eq!(handles.ids, ids);
+        graph.update();
+    }
+
+    // Stands in for an alsa device with the rate and channels its config
+    // asks for.
+    fn stub_alsa_format(config: &NodeConfig) -> StreamFormat {
+        let hw_params = config.get("hw_params");
+        let field = |field: &str, default: u64| {
+            hw_params.and_then(|json| json.find(field)).and_then(|value| value.as_u64()).unwrap_or(default) as usize
+        };
+        StreamFormat::new(field("rate", 48000), field("channels", 2))
+    }
+
+    #[test]
+    fn it_loads_the_shipped_rig() {
+        let config = GraphConfig::from_str(include_str!("../../rigs/full.json")).unwrap();
+        let mut loader = GraphLoader::new(ActivationController::new());
+        loader.add_kind("alsa_playback", Box::new(|config: &NodeConfig| {
+            let node: Box<Node> = RenderSink::new(stub_alsa_format(config));
+            Ok(node)
+        }));
+        loader.add_kind("alsa_capture", Box::new(|config: &NodeConfig| {
+            let node: Box<Node> = RenderSource::new(Vec::new(), 0, stub_alsa_format(config));
+            Ok(node)
+        }));
+        let mut graph = Graph::new();
+        let handles = loader.load(&config, &mut graph).unwrap();
+        assert_eq!(handles.ids.len(), config.nodes.len());
+        graph.update();
+    }
+
+    #[test]
+    fn it_reports_bad_configs() {
+        let mut graph = Graph::new();
+        let loader = GraphLoader::new(ActivationController::new());
+
+        let config = GraphConfig::from_str(r#"{"nodes": [{"name": "a", "kind": "reverb"}]}"#).unwrap();
+        match loader.load(&config, &mut graph) {
+            Err(ConfigError::UnknownKind(name, kind)) => assert_eq!((name.as_str(), kind.as_str()), ("a", "reverb")),
+            _ => panic!("expected an unknown kind"),
+        }
+
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix", "to": ["b"]},
+            {"name": "b", "kind": "volume", "volume": [1, 0]}
+        ]}"#).unwrap();
+        match loader.load(&config, &mut graph) {
+            Err(ConfigError::InvalidField(name, "volume")) => assert_eq!(name, "b"),
+            _ => panic!("expected an invalid volume"),
+        }
+
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix", "to": ["b"]},
+            {"name": "b", "kind": "base_mix", "to": ["a"]}
+        ]}"#).unwrap();
+        match loader.load(&config, &mut graph) {
+            Err(ConfigError::Graph(_)) => {},
+            _ => panic!("expected a cycle"),
+        }
+
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix", "to": ["b"]},
+            {"name": "b", "kind": "base_mix", "feedback_to": ["a"]}
+        ]}"#).unwrap();
+        assert!(loader.load(&config, &mut graph).is_ok());
+
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "base_mix", "to": ["b:sidechain"]},
+            {"name": "b", "kind": "base_mix"}
+        ]}"#).unwrap();
+        match loader.load(&config, &mut graph) {
+            Err(ConfigError::UnknownPort(ref node, ref port)) if node == "b" && port == "sidechain" => {},
+            _ => panic!("expected a missing port"),
+        }
+
+        let config = GraphConfig::from_str(r#"{"nodes": [
+            {"name": "a", "kind": "rate", "input_rate": 48000, "output_rate": 44100, "to": ["b"]},
+            {"name": "b", "kind": "rate", "input_rate": 48000, "output_rate": 44100}
+        ]}"#).unwrap();
+        match loader.load(&config, &mut graph) {
+            Err(ConfigError::Graph(GraphError::FormatMismatch(_, output_format, _, input_format))) => {
+                assert_eq!((output_format.rate, input_format.rate), (Some(44100), Some(48000)));
+            },
+            _ => panic!("expected mismatched rates"),
+        }
+
+        for kind in [r#""duck", "state": "mic""#, r#""sidechain_ducked", "volume": [1, 5]"#].iter() {
+            let config = GraphConfig::from_str(&format!(r#"{{"nodes": [{{"name": "a", "kind": {}, "peak": 40000}}]}}"#, kind)).unwrap();
+            match loader.load(&config, &mut graph) {
+                Err(ConfigError::InvalidField(name, "peak")) => assert_eq!(name, "a"),
+                _ => panic!("expected an invalid peak"),
+            }
+        }
+
+        for port in ["\"9000\"", "9000.5", "0", "70000"].iter() {
+            let config = GraphConfig::from_str(&format!(r#"{{"nodes": [{{"name": "a", "kind": "io", "tcp_port": {}}}]}}"#, port)).unwrap();
+            match loader.load(&config, &mut graph) {
+                Err(ConfigError::InvalidField(name, "tcp_port")) => assert_eq!(name, "a"),
+                _ => panic!("expected an invalid tcp_port"),
+            }
+        }
+
+        assert!(GraphConfig::from_str("{").is_err());
+    }
+}
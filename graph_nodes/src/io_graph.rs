@@ -1,35 +1,57 @@
 use std::sync::{Arc, Mutex, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 use std::io;
 use std::io::{ErrorKind, Read};
 use std::slice;
 
-use graph_utils::{Node, RingBuffer, Capture};
+use graph_utils::{Node, NodeInfo, RingBuffer, Capture, Clock, RingProducer, RingConsumer, spsc_ring};
 
 use activation::*;
 
 pub struct IoNodeBuffer {
-    name: &'static str,
+    name: String,
     activation_controller: ActivationController,
-    stream_mutex: Arc<Mutex<RingBuffer>>,
+    clock: Clock,
+    // The reader thread writes into producer and the capture reads from
+    // consumer without either waiting on the other.
+    producer: Arc<Mutex<RingProducer>>,
+    consumer: Mutex<Option<RingConsumer>>,
     last_tick: Instant,
     reader_tick: Arc<(Mutex<i32>, Condvar, Mutex<bool>, Condvar)>,
     should_shutdown: Arc<Mutex<bool>>,
 }
 
 pub struct IoReadFactory {
-    name: &'static str,
-    stream_mutex: Arc<Mutex<RingBuffer>>,
+    name: String,
+    clock: Clock,
+    producer: Arc<Mutex<RingProducer>>,
     reader_tick: Arc<(Mutex<i32>, Condvar, Mutex<bool>, Condvar)>,
     should_shutdown: Arc<Mutex<bool>>,
 }
 
-pub struct IoCapture(Capture);
+// The capture and a flag telling it to start over from waiting for samples.
+pub struct IoCapture(Capture, Arc<AtomicBool>);
 
 impl Node for IoCapture {
     fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
         self.0.update(inputs, outputs);
     }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo {
+            kind: "io",
+            ..self.0.info()
+        }
+    }
+
+    fn reset(&mut self) {
+        self.1.store(true, Ordering::Relaxed);
+    }
+
+    fn flush(&mut self) {
+        self.0.flush();
+    }
 }
 
 pub type IoReader = Box<Fn(&mut io::Read) + Send + Sync>;
@@ -44,12 +66,20 @@ impl Drop for IoNodeBuffer {
 }
 
 impl IoNodeBuffer {
-    pub fn new(name: &'static str, activation_controller: ActivationController) -> IoNodeBuffer {
+    pub fn new(name: &str, activation_controller: ActivationController) -> IoNodeBuffer {
+        IoNodeBuffer::with_clock(name, activation_controller, Clock::system())
+    }
+
+    // Captures time out on the given clock instead of the system's.
+    pub fn with_clock(name: &str, activation_controller: ActivationController, clock: Clock) -> IoNodeBuffer {
+        let (producer, consumer) = spsc_ring(16384);
         IoNodeBuffer {
-            name: name,
+            name: name.to_string(),
             activation_controller: activation_controller,
-            stream_mutex: Arc::new(Mutex::new(RingBuffer::new())),
-            last_tick: Instant::now(),
+            clock: clock.clone(),
+            producer: Arc::new(Mutex::new(producer)),
+            consumer: Mutex::new(Some(consumer)),
+            last_tick: clock.now(),
             reader_tick: Arc::new((Mutex::new(-1), Condvar::new(), Mutex::new(false), Condvar::new())),
             should_shutdown: Arc::new(Mutex::new(false)),
         }
@@ -62,30 +92,36 @@ impl IoNodeBuffer {
         }
     }
 
-    pub fn capture(&self) -> Box<IoCapture> {
-        let name = self.name;
-        let stream_mutex = self.stream_mutex.clone();
+    // The capture reading what the reader thread writes, or None if the
+    // buffer already made it.
+    pub fn capture(&self) -> Option<Box<IoCapture>> {
+        let name = self.name.clone();
+        let mut consumer = match self.consumer.lock().unwrap().take() {
+            Some(consumer) => consumer,
+            None => return None,
+        };
         let activation_controller_clone = self.activation_controller.clone();
         let mut activation_guard = None;
+        let clock = self.clock.clone();
 
-        let mut last_received = Instant::now();
+        let mut last_received = clock.now();
         let mut state = 0;
         let mut paused = false;
         let mut samples = 0;
+        let reset = Arc::new(AtomicBool::new(false));
+        let reset_clone = reset.clone();
 
-        Box::new(IoCapture(Capture::new(Box::new(move |output| {
-            let music_len = {
-                if let Ok(mut http_music) = (*stream_mutex).try_lock() {
-                    let len = http_music.len();
-                    if state != 2 {
-                        http_music.clear();
-                    }
-                    len
-                }
-                else {
-                    0
-                }
-            };
+        Some(Box::new(IoCapture(Capture::new(Box::new(move |output| {
+            if reset_clone.swap(false, Ordering::Relaxed) {
+                activation_guard = None;
+                paused = false;
+                state = 0;
+            }
+
+            let music_len = consumer.len();
+            if state != 2 {
+                consumer.clear();
+            }
 
             output.active = false;
 
@@ -93,7 +129,7 @@ impl IoNodeBuffer {
                 activation_guard = activation_controller_clone.activate();
                 if activation_guard.is_some() {
                     println!("activating http {}", name);
-                    last_received = Instant::now();
+                    last_received = clock.now();
                     samples = 0;
                     state = 1;
                 }
@@ -104,20 +140,20 @@ impl IoNodeBuffer {
                 samples = 0;
                 state = 2;
             }
-            else if state == 1 && music_len == 0 && Instant::now().duration_since(last_received).as_secs() > 3 {
+            else if state == 1 && music_len == 0 && clock.now().duration_since(last_received).as_secs() > 3 {
                 activation_guard = None;
                 println!("didn't activate http {}", name);
                 state = 0;
             }
             else if state == 1 && music_len > 0 {
-                last_received = Instant::now();
+                last_received = clock.now();
                 samples += music_len;
             }
             else if state == 1 {
                 // samples += 20;
             }
             else if state == 2 && music_len > 0 {
-                last_received = Instant::now();
+                last_received = clock.now();
                 samples = 0;
 
                 match activation_controller_clone.is_activating() {
@@ -138,29 +174,29 @@ impl IoNodeBuffer {
 
                 output.active = !paused;
 
-                if let Ok(mut http_music) = (*stream_mutex).try_lock() {
-                    if !paused {
-                        output.write_from_ring((*http_music).len(), &mut *http_music);
-                    }
-                    else {
-                        (*http_music).clear();
-                    }
+                if !paused {
+                    let avail = consumer.len();
+                    consumer.read_into_ring(avail, output);
+                }
+                else {
+                    consumer.clear();
                 }
             }
             else if state == 2 && music_len == 0 && samples < 48000 {
                 // samples += 20;
                 output.active = !paused;
             }
-            else if state == 2 && music_len == 0 && Instant::now().duration_since(last_received).as_secs() >= 2 {
+            else if state == 2 && music_len == 0 && clock.now().duration_since(last_received).as_secs() >= 2 {
                 state = 0;
             }
-        }))))
+        })).with_name(&self.name), reset)))
     }
 
     pub fn read_factory(&self) -> IoReadFactory {
         IoReadFactory {
-            name: self.name,
-            stream_mutex: self.stream_mutex.clone(),
+            name: self.name.clone(),
+            clock: self.clock.clone(),
+            producer: self.producer.clone(),
             reader_tick: self.reader_tick.clone(),
             should_shutdown: self.should_shutdown.clone(),
         }
@@ -169,8 +205,9 @@ impl IoNodeBuffer {
 
 impl IoReadFactory {
     pub fn reader(&self) -> Box<Fn(&mut io::Read) + Send + Sync> {
-        let name = self.name;
-        let stream_mutex = self.stream_mutex.clone();
+        let name = self.name.clone();
+        let clock = self.clock.clone();
+        let producer = self.producer.clone();
         let should_shutdown = self.should_shutdown.clone();
         let net_tick_pair = self.reader_tick.clone();
 
@@ -189,7 +226,6 @@ impl IoReadFactory {
             for _ in 0..16384 {
                 buffer.push(0);
             }
-            let mut inner = RingBuffer::new();
 
             // sleep(Duration::from_millis(20));
             let mut sample_error = 0;
@@ -202,6 +238,8 @@ impl IoReadFactory {
                 return;
             }
             *net_guard = 0;
+            // Only one reader runs at a time, so this never waits.
+            let mut producer = producer.lock().unwrap();
             // {
             //     let mut net_out_guard = net_out_mutex.lock().unwrap();
             //     *net_out_guard = true;
@@ -209,7 +247,7 @@ impl IoReadFactory {
             // println!("{} wait", name);
             net_guard = net_condvar.wait(net_guard).unwrap();
 
-            let mut last_read = Instant::now();
+            let mut last_read = clock.now();
             loop {
                 if let Ok(should_shutdown) = should_shutdown.lock() {
                     if *should_shutdown {
@@ -228,17 +266,16 @@ impl IoReadFactory {
                         stream.read(slice)
                     } {
                         Ok(read) => {
-                            let now = Instant::now();
                             if read > 0 {
-                                if let Ok(mut http_music) = stream_mutex.try_lock() {
-                                    last_read = now;
-                                    http_music.write_from(read / 2, &buffer);
-                                }
-                                else {
-                                    inner.write_from(read / 2, &buffer);
+                                last_read = clock.now();
+                                // Writes keep falling short until the capture
+                                // takes the discard, so only the first says so.
+                                if producer.write(&buffer[..read / 2]) < read / 2 && !producer.discarding() {
+                                    println!("cleaning build up in {:?} stream", name);
+                                    producer.discard();
                                 }
                             }
-                            if now.duration_since(start).subsec_nanos() > 500000 {
+                            if Instant::now().duration_since(start).subsec_nanos() > 500000 {
                                 // print!("read for 2ms ");
                                 break;
                             }
@@ -257,10 +294,7 @@ impl IoReadFactory {
                                 break;
                             }
                             did_error = true;
-                            if let Ok(mut http_music) = (*stream_mutex).lock() {
-                                (*http_music).clear();
-                                // print!("read{:?}", read / 2 / 2);
-                            }
+                            producer.discard();
                             println!("break {:?} error: {:?}", name, err);
                             break;
                         },
@@ -270,39 +304,16 @@ impl IoReadFactory {
                 if did_error {
                     break;
                 }
-                if Instant::now().duration_since(last_read).as_secs() > 1 {
+                if clock.now().duration_since(last_read).as_secs() > 1 {
                 // if samples_missed > 48000 {
                     samples_missed = 0;
-                    if let Ok(mut http_music) = (*stream_mutex).lock() {
-                        (*http_music).clear();
-                        // print!("read{:?}", read / 2 / 2);
-                    }
+                    producer.discard();
                     println!("break {:?} no data after a second", name);
                     break;
                 }
                 // else {
                 //     samples_missed += 1;
                 // }
-                if inner.len() > 0 {
-                    // println!("sending {} upstream", name);
-                    // samples_missed = 0;
-                    last_read = Instant::now();
-                    if let Ok(mut http_music) = (*stream_mutex).lock() {
-                        if (*http_music).len() < 16384 {
-                            (*http_music).write_from_ring(inner.len(), &mut inner);
-                        }
-                        else {
-                            println!("cleaning build up in {:?} stream", name);
-                            inner.clear();
-                        }
-                        // print!("read{:?}", read / 2 / 2);
-                    }
-                    else {
-                        println!("couldn't lock upstream channel");
-                        break;
-                    }
-                }
-
                 // {
                 //     let net_out_guard = net_out_mutex.lock().unwrap();
                 //     net_out_condvar.notify_one();
@@ -1,9 +1,12 @@
 extern crate graph_utils;
+extern crate rustc_serialize;
 
 mod activation;
 mod channels;
 mod duck;
+mod fade_in;
 mod gated;
+mod graph_config;
 mod io_graph;
 mod rate;
 mod volume;
@@ -11,7 +14,9 @@ mod volume;
 pub use self::activation::*;
 pub use self::channels::*;
 pub use self::duck::*;
+pub use self::fade_in::*;
 pub use self::gated::*;
+pub use self::graph_config::*;
 pub use self::io_graph::*;
 pub use self::rate::*;
 pub use self::volume::*;
@@ -1,52 +1,104 @@
-use graph_utils::{Callback, CallbackInner, RingBuffer};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-pub struct Rate(Callback);
+use graph_utils::{Callback, CallbackInner, Frame, Node, StreamFormat};
+
+// Resamples frames of interleaved channels from input_rate to output_rate.
+// It reads as many channels as the graph hands it, 2 until it knows.
+pub struct Rate {
+    callback: Callback,
+    input_rate: usize,
+    output_rate: usize,
+    channels: Arc<AtomicUsize>,
+}
 
 impl CallbackInner for Rate {
     fn get_callback(&mut self) -> &mut Callback {
-        &mut self.0
+        &mut self.callback
+    }
+
+    fn input_format(&self) -> StreamFormat {
+        StreamFormat::any().with_rate(self.input_rate)
+    }
+
+    fn output_format(&self, input: StreamFormat) -> StreamFormat {
+        input.with_rate(self.output_rate)
+    }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        let channels = format.channels.unwrap_or(2);
+        if self.channels.swap(channels, Ordering::Relaxed) != channels {
+            // Start over with frames of the new size.
+            Node::reset(&mut self.callback);
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "rate"
     }
 }
 
 impl Rate {
     pub fn new(input_rate: usize, output_rate: usize) -> Box<Rate> {
-        let input_upper = input_rate * 2 / 1000;
-        let input_lower = input_rate * 2 % 1000;
+        // Frames per millisecond and the thousandths of a frame left over,
+        // carried until they add up to whole frames.
+        let input_upper = input_rate / 1000;
+        let input_lower = input_rate % 1000;
         let input_upper_max = input_upper + if input_lower != 0 {1} else {0};
-        let mut input_carry = 0;
-        let output_upper = output_rate * 2 / 1000;
-        let output_lower = output_rate * 2 % 1000;
-        let output_upper_max = output_upper + if output_lower != 0 {1} else {0};
-        let mut output_carry = 0;
-        Box::new(Rate(Callback::new(Box::new(move |input, output| {
-            let mut avail = input.len();
-
-            let mut num = 0;
-            let mut denom = 0;
-            while num + input_upper_max <= avail {
-                num += input_upper;
-                input_carry += input_lower;
-                if input_carry >= 2000 {
-                    let diff = input_carry / 2000;
-                    input_carry -= diff * 2000;
-                    num += diff * 2;
-                }
-                denom += output_upper;
-                output_carry += output_lower;
-                if output_carry >= 2000 {
-                    let diff = output_carry / 2000;
-                    output_carry -= diff * 2000;
-                    denom += diff * 2;
+        let output_upper = output_rate / 1000;
+        let output_lower = output_rate % 1000;
+        let channels = Arc::new(AtomicUsize::new(2));
+        let factory_channels = channels.clone();
+        // The carry starts over when the node is reset.
+        let callback = Callback::resettable(Box::new(move || {
+            let channels = factory_channels.load(Ordering::Relaxed);
+            let mut input_carry = 0;
+            let mut output_carry = 0;
+            let mut input_frames = Frame::new(channels);
+            let mut output_frames = Frame::new(channels);
+            Box::new(move |input, output| {
+                let avail = input.frames(channels);
+
+                let mut num = 0;
+                let mut denom = 0;
+                while num + input_upper_max <= avail {
+                    num += input_upper;
+                    input_carry += input_lower;
+                    if input_carry >= 1000 {
+                        let diff = input_carry / 1000;
+                        input_carry -= diff * 1000;
+                        num += diff;
+                    }
+                    denom += output_upper;
+                    output_carry += output_lower;
+                    if output_carry >= 1000 {
+                        let diff = output_carry / 1000;
+                        output_carry -= diff * 1000;
+                        denom += diff;
+                    }
                 }
-            }
 
-            let slice = input.read_slice(num);
-            let mut out_slice = output.write_slice(denom);
+                input_frames.read_from(input, num);
+                resample(&input_frames, &mut output_frames, denom);
+                output_frames.write_to(output);
+            })
+        }));
+        Box::new(Rate {
+            callback: callback,
+            input_rate: input_rate,
+            output_rate: output_rate,
+            channels: channels,
+        })
+    }
+}
 
-            for (index, o) in out_slice.iter_mut().enumerate() {
-                *o = slice[index / 2 * num / denom * 2 + index % 2];
-            }
-        }))))
+// Replace output with frames input frames resampled to the nearest input
+// frame for each.
+fn resample(input: &Frame, output: &mut Frame, frames: usize) {
+    output.clear();
+    let num = input.len();
+    for index in 0..frames {
+        output.push_frame().copy_from_slice(input.frame(index * num / frames));
     }
 }
 
@@ -76,3 +128,77 @@ impl Rate {
 //         }
 //     })))
 // };
+
+#[cfg(test)]
+mod test {
+    use graph_utils::{Graph, GraphNodeParams, Node, RenderSink, RenderSource, RingBuffer, StreamFormat};
+
+    use super::Rate;
+
+    // A ring of max_length samples whose next write starts offset samples
+    // before its end.
+    fn ring_ending_in(max_length: usize, offset: usize) -> RingBuffer {
+        let mut ring = RingBuffer::new();
+        ring.max_length = max_length;
+        let skip = max_length + 1 - offset;
+        ring.write_from(skip, &vec!(0; skip));
+        ring.read_slice(skip);
+        ring
+    }
+
+    // 96 stereo frames at 48000 Hz, and the 48 at 24000 Hz they resample to.
+    fn frames_48000_to_24000() -> (Vec<i16>, Vec<i16>) {
+        let input = (0..192).collect::<Vec<i16>>();
+        let output = (0..48).flat_map(|frame| vec!(frame * 4, frame * 4 + 1)).collect::<Vec<i16>>();
+        (input, output)
+    }
+
+    fn read_all(ring: &mut RingBuffer) -> Vec<i16> {
+        let avail = ring.len();
+        let mut samples = Vec::new();
+        ring.read_into(avail, &mut samples);
+        samples
+    }
+
+    #[test]
+    fn it_resamples_nothing_from_empty_input() {
+        let mut rate = Rate::new(48000, 24000);
+        let mut inputs = vec!(RingBuffer::new());
+        let mut outputs = vec!(RingBuffer::new());
+        rate.update(&mut inputs, &mut outputs);
+        assert_eq!(outputs[0].len(), 0);
+    }
+
+    #[test]
+    fn it_resamples_input_wrapping_the_ring_end() {
+        let (input, output) = frames_48000_to_24000();
+        let mut rate = Rate::new(48000, 24000);
+        let mut inputs = vec!(ring_ending_in(255, 63));
+        inputs[0].write_from(input.len(), &input);
+        let mut outputs = vec!(RingBuffer::new());
+        rate.update(&mut inputs, &mut outputs);
+        assert_eq!(inputs[0].len(), 0);
+        assert_eq!(read_all(&mut outputs[0]), output);
+    }
+
+    #[test]
+    fn it_resamples_into_output_wrapping_the_ring_end() {
+        let (input, output) = frames_48000_to_24000();
+        let mut rate = Rate::new(48000, 24000);
+        let mut inputs = vec!(RingBuffer::new());
+        inputs[0].write_from(input.len(), &input);
+        let mut outputs = vec!(ring_ending_in(127, 31));
+        rate.update(&mut inputs, &mut outputs);
+        assert_eq!(read_all(&mut outputs[0]), output);
+    }
+
+    #[test]
+    fn it_resamples_the_channels_it_is_given() {
+        let mut g = Graph::new();
+        let sink_id = g.connect(RenderSink::new(StreamFormat::new(24000, 1)), Default::default());
+        let rate_id = g.connect(Rate::new(48000, 24000), GraphNodeParams::default().to(sink_id));
+        g.connect(RenderSource::new((0..480).collect(), 480, StreamFormat::new(48000, 1)), GraphNodeParams::default().to(rate_id));
+        g.update();
+        assert_eq!(g.get(sink_id).unwrap().samples().to_vec(), (0..240).map(|i| i * 2).collect::<Vec<i16>>());
+    }
+}
@@ -1,20 +1,84 @@
-use graph_utils::{Callback, CallbackInner, RingBuffer};
+use std::sync::{Arc, Mutex};
 
-pub struct Volume(Callback);
+use graph_utils::{Callback, CallbackInner, Node, ParamRW, StreamFormat, SMOOTHING};
+
+// Scales its input by a volume that can be set while it runs, and the format
+// ramps scheduled on the volume are timed in.
+pub struct Volume(Callback, ParamRW, Arc<Mutex<StreamFormat>>);
 
 impl CallbackInner for Volume {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        let changed = {
+            let mut current = self.2.lock().unwrap();
+            let changed = *current != format;
+            *current = format;
+            changed
+        };
+        if changed {
+            Node::reset(&mut self.0);
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        "volume"
+    }
 }
 
 impl Volume {
     pub fn new((num, denom): (i32, i32)) -> Box<Volume> {
-        Box::new(Volume(Callback::new(Box::new(move |input, output| {
-            let avail = input.len();
-            for (i, o) in input.read_slice(avail).iter().zip(output.write_slice(avail).iter_mut()) {
-                *o = (*i as i32 * num / denom) as i16;
-            }
-        }))))
+        Volume::with_param(ParamRW::new(num as f32 / denom as f32))
+    }
+
+    // Changes to volume are spread over SMOOTHING samples so they don't
+    // click.
+    pub fn with_param(volume: ParamRW) -> Box<Volume> {
+        let read_volume = volume.clone();
+        let format = Arc::new(Mutex::new(StreamFormat::any()));
+        let factory_format = format.clone();
+        Box::new(Volume(Callback::resettable(Box::new(move || {
+            let mut volume = read_volume.clone_read(SMOOTHING).with_format(*factory_format.lock().unwrap());
+            Box::new(move |input, output| {
+                volume.update();
+                let avail = input.len();
+                input.read_slice(avail).zip_runs(&mut output.write_slice(avail), |input, output| volume.apply(input, output));
+            })
+        })), volume, format))
+    }
+
+    pub fn volume(&self) -> &ParamRW {
+        &self.1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use graph_utils::{Clock, Graph, GraphNodeParams, OfflineRender, RenderSink};
+
+    use super::Volume;
+
+    #[test]
+    fn it_times_ramps_in_its_input_format() {
+        // 10ms blocks of 44100 Hz mono.
+        let render = OfflineRender::new(Clock::simulated(), 44100, 1, 441);
+        let mut g = Graph::new();
+        let source_id = g.connect(render.source(vec!(1000; 441 * 20)), Default::default()).id();
+        let volume = Volume::new((1, 1));
+        volume.volume().linear_ramp(0.5, Duration::from_millis(100));
+        let volume_id = g.connect(volume, GraphNodeParams::default().from(source_id)).id();
+        let sink_id = g.connect(render.sink(), GraphNodeParams::default().from(volume_id)).id();
+        render.render(&mut g, 20);
+
+        let sink = g.borrow(sink_id).downcast_ref::<RenderSink>().unwrap();
+        assert_eq!(sink.samples().len(), 441 * 20);
+        // 100ms is 4410 samples, where 96000 a second would take 9600.
+        assert!(sink.samples()[4000] > 520);
+        assert_eq!(sink.samples()[4409], 500);
+        assert_eq!(sink.samples()[441 * 20 - 1], 500);
     }
 }
@@ -1,20 +1,26 @@
 use std::cmp::min;
 use std::time::{Instant, Duration};
 
-use super::{Node, RingBuffer, copy_out};
+use super::{Node, NodeInfo, RingBuffer, Sample, TimeMark, copy_out};
 
-pub struct BaseMix {
-    pub accum: Vec<i16>,
-    read_copy: Vec<i16>,
+// Mixes inputs into accum. The graph's edges carry i16 samples, so its nodes
+// mix BaseMix<i16>. A BaseMix of a wider type, like i32 or f32, only leaves
+// headroom when mixing rings of that type outside the graph.
+pub struct BaseMix<T: Sample = i16> {
+    pub accum: Vec<T>,
+    read_copy: Vec<T>,
+    // the inputs' marks on the samples in accum
+    marks: Vec<(usize, TimeMark)>,
     // last: Instant,
     // avail_error: usize,
 }
 
-impl Default for BaseMix {
-    fn default() -> BaseMix {
+impl<T: Sample> Default for BaseMix<T> {
+    fn default() -> BaseMix<T> {
         BaseMix {
-            accum: Vec::<i16>::new(),
-            read_copy: Vec::<i16>::new(),
+            accum: Vec::<T>::new(),
+            read_copy: Vec::<T>::new(),
+            marks: Vec::new(),
             // last: Instant::now(),
             // avail_error: 0,
         }
@@ -25,8 +31,10 @@ impl BaseMix {
     pub fn new() -> BaseMix {
         BaseMix { ..Default::default() }
     }
+}
 
-    pub fn mix_inputs(&mut self, inputs: &mut [RingBuffer]) -> usize {
+impl<T: Sample> BaseMix<T> {
+    pub fn mix_inputs(&mut self, inputs: &mut [RingBuffer<T>]) -> usize {
         // let now = Instant::now()
         let avail = {
             // inputs.iter().fold(usize::max_value(), |a, v| min(a, v.len()))
@@ -39,12 +47,13 @@ impl BaseMix {
         };
         // print!("{:?} {:?} ", avail, inputs.iter().map(|x| (x.active, x.len())).collect::<Vec<(bool, usize)>>());
         for _ in self.accum.len()..avail {
-            self.accum.push(0);
+            self.accum.push(T::default());
             // self.read_copy.push(0);
         }
-        for i in 0..avail {
-            self.accum[i] = 0;
+        for a in self.accum[..avail].iter_mut() {
+            *a = T::default();
         }
+        self.marks.clear();
         let num_inputs = inputs.iter().filter(|x| x.active).count();
         // if num_inputs == 1 {
         //
@@ -56,18 +65,27 @@ impl BaseMix {
                     // for i in 0..avail {
                     //     self.read_copy[i] = 0;
                     // }
+                    input.peek_marks(avail, &mut self.marks);
                     let slice = input.read_slice(avail);
                     // input.read_into(avail, &mut self.read_copy);
-                    for i in 0..avail {
-                        self.accum[i] += slice[i];
-                    }
+                    let (first, second) = slice.as_slices();
+                    mix_run(&mut self.accum[..first.len()], first);
+                    mix_run(&mut self.accum[first.len()..avail], second);
                 }
             }
         // }
         avail
     }
 
-    pub fn mix_inputs_ring(&mut self, inputs: &mut [RingBuffer], ring: &mut RingBuffer) {
+    // Attach the marks on the last mix to the next samples written to ring.
+    pub fn mark(&self, ring: &mut RingBuffer<T>) {
+        let position = ring.written();
+        for &(offset, ref mark) in self.marks.iter() {
+            ring.mark(position + offset as u64, mark.clone());
+        }
+    }
+
+    pub fn mix_inputs_ring(&mut self, inputs: &mut [RingBuffer<T>], ring: &mut RingBuffer<T>) {
         let num_inputs = inputs.iter().filter(|x| x.active).count();
         if num_inputs == 0 {
             ring.active = false;
@@ -82,19 +100,35 @@ impl BaseMix {
         else {
             let avail = self.mix_inputs(inputs);
             ring.active = inputs.len() > 0 && inputs.iter().any(|x| x.active);
+            self.mark(ring);
             ring.write_from(avail, &self.accum);
         }
     }
 }
 
+// Mix input into accum, sample for sample.
+fn mix_run<T: Sample>(accum: &mut [T], input: &[T]) {
+    for (a, i) in accum.iter_mut().zip(input.iter()) {
+        *a = a.mix(*i);
+    }
+}
+
 impl Node for BaseMix {
     fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
-        copy_out(self.mix_inputs(inputs), &mut self.accum, outputs);
+        let avail = self.mix_inputs(inputs);
+        for output in outputs.iter_mut() {
+            self.mark(output);
+        }
+        copy_out(avail, &mut self.accum, outputs);
         let active = inputs.len() > 0 && inputs.iter().any(|x| x.active);
         for output in outputs.iter_mut() {
             output.active = active
         }
     }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("base_mix")
+    }
 }
 
 #[cfg(test)]
@@ -116,4 +150,17 @@ mod test {
         }
         assert_eq!(a.accum[0], 144);
     }
+
+    #[test]
+    fn it_mixes_with_headroom() {
+        let mut inputs = vec!(RingBuffer::from(vec!(30000i16)), RingBuffer::from(vec!(30000i16)));
+        let mut a = BaseMix::new();
+        a.mix_inputs(&mut inputs);
+        assert_eq!(a.accum[0], 32767);
+
+        let mut inputs = vec!(RingBuffer::from(vec!(0.75f32)), RingBuffer::from(vec!(0.75f32)));
+        let mut b = BaseMix::<f32>::default();
+        b.mix_inputs(&mut inputs);
+        assert_eq!(b.accum[0], 1.5);
+    }
 }
@@ -1,17 +1,66 @@
 use std::any::Any;
 
-use super::{Node, RingBuffer, copy_out_ring, BaseMix};
+use super::{Node, NodeInfo, RingBuffer, StreamFormat, TimeMark, copy_out_ring, BaseMix};
 
-type CallbackFn = Box<FnMut(&mut RingBuffer, &mut RingBuffer)>;
+// Callbacks read and write the graph's i16 samples. Other sample types are
+// converted to and from where they enter and leave the graph, in Capture and
+// Playback.
+pub type CallbackFn = Box<FnMut(&mut RingBuffer, &mut RingBuffer) + Send>;
+// Makes a callback starting from nothing, so resetting the Callback can make
+// a new one instead of keeping state the old one closed over.
+pub type CallbackFactory = Box<Fn() -> CallbackFn + Send>;
 
 pub struct Callback {
     base_mix: BaseMix,
     tmp_state: Option<(RingBuffer, RingBuffer, Vec<i16>)>,
     callback: CallbackFn,
+    factory: Option<CallbackFactory>,
+    // marks on what the callback is given to read
+    marks: Vec<(usize, TimeMark)>,
 }
 
-pub trait CallbackInner : Any {
+pub trait CallbackInner : Any + Send {
     fn get_callback(&mut self) -> &mut Callback;
+    // See Node::input_format, Node::output_format and
+    // Node::set_input_format.
+    fn input_format(&self) -> StreamFormat {
+        StreamFormat::any()
+    }
+    fn output_format(&self, input: StreamFormat) -> StreamFormat {
+        input
+    }
+    fn set_input_format(&mut self, _: StreamFormat) {}
+    // The kind in the node's Node::info.
+    fn kind(&self) -> &'static str {
+        "callback"
+    }
+    // See Node::start, Node::stop, Node::reset and Node::flush.
+    fn start(&mut self) {}
+    fn stop(&mut self) {}
+    fn reset(&mut self) {
+        Node::reset(self.get_callback());
+    }
+    fn flush(&mut self) {
+        Node::flush(self.get_callback());
+    }
+}
+
+// Call callback and carry the marks on what it read over to what it wrote.
+// The marks are spread over what it wrote when it writes more or less than it
+// reads, like Rate.
+fn call_back(callback: &mut CallbackFn, marks: &mut Vec<(usize, TimeMark)>, input: &mut RingBuffer, output: &mut RingBuffer) {
+    let avail = input.len();
+    marks.clear();
+    input.peek_marks(avail, marks);
+    let position = output.written();
+    callback(input, output);
+    let read = avail - input.len();
+    let wrote = (output.written() - position) as usize;
+    if wrote > 0 {
+        for &(offset, ref mark) in marks.iter().filter(|&&(offset, _)| offset < read) {
+            output.mark(position + (offset * wrote / read) as u64, mark.clone());
+        }
+    }
 }
 
 impl Callback {
@@ -20,6 +69,30 @@ impl Callback {
             base_mix: BaseMix::new(),
             tmp_state: Some((RingBuffer::new(), RingBuffer::new(), Vec::<i16>::new())),
             callback: callback,
+            factory: None,
+            marks: Vec::new(),
+        }
+    }
+
+    // A callback made by factory, and made again when the node is reset.
+    pub fn resettable(factory: CallbackFactory) -> Callback {
+        let callback = factory();
+        Callback {
+            factory: Some(factory),
+            ..Callback::new(callback)
+        }
+    }
+
+    fn _reset(&mut self) {
+        if let Some(ref factory) = self.factory {
+            self.callback = factory();
+        }
+    }
+
+    fn _flush(&mut self) {
+        if let Some((ref mut in_buffer, ref mut out_buffer, _)) = self.tmp_state {
+            in_buffer.clear();
+            out_buffer.clear();
         }
     }
 }
@@ -40,6 +113,39 @@ impl<T : CallbackInner> Node for T {
     fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
         self.get_callback().update(inputs, outputs);
     }
+
+    fn input_format(&self) -> StreamFormat {
+        CallbackInner::input_format(self)
+    }
+
+    fn output_format(&self, input: StreamFormat) -> StreamFormat {
+        CallbackInner::output_format(self, input)
+    }
+
+    fn set_input_format(&mut self, format: StreamFormat) {
+        CallbackInner::set_input_format(self, format);
+    }
+
+    fn info(&self) -> NodeInfo {
+        let format = CallbackInner::output_format(self, CallbackInner::input_format(self));
+        NodeInfo::new(CallbackInner::kind(self)).with_format(format)
+    }
+
+    fn start(&mut self) {
+        CallbackInner::start(self);
+    }
+
+    fn stop(&mut self) {
+        CallbackInner::stop(self);
+    }
+
+    fn reset(&mut self) {
+        CallbackInner::reset(self);
+    }
+
+    fn flush(&mut self) {
+        CallbackInner::flush(self);
+    }
 }
 
 impl Node for Callback {
@@ -51,27 +157,27 @@ impl Node for Callback {
             let (mut in_buffer, mut out_buffer, mut sub_buffer) = self.tmp_state.take().unwrap();
             let mut output = &mut outputs[0];
             output.active = in_buffer.active;
-            (self.callback)(&mut in_buffer, output);
+            call_back(&mut self.callback, &mut self.marks, &mut in_buffer, output);
             self.tmp_state = Some((in_buffer, out_buffer, sub_buffer));
         }
         if inputs.len() == 1 && outputs.len() == 0 {
             let (mut in_buffer, mut out_buffer, mut sub_buffer) = self.tmp_state.take().unwrap();
             let mut input = &mut inputs[0];
             out_buffer.active = input.active;
-            (self.callback)(input, &mut out_buffer);
+            call_back(&mut self.callback, &mut self.marks, input, &mut out_buffer);
             self.tmp_state = Some((in_buffer, out_buffer, sub_buffer));
         }
         else if inputs.len() == 1 && outputs.len() == 1 {
             let mut input = &mut inputs[0];
             let mut output = &mut outputs[0];
             output.active = input.active;
-            (self.callback)(input, output);
+            call_back(&mut self.callback, &mut self.marks, input, output);
         }
         else if inputs.len() == 1 {
             let (mut in_buffer, mut out_buffer, mut sub_buffer) = self.tmp_state.take().unwrap();
             let ref mut input = inputs[0];
             out_buffer.active = input.active;
-            (self.callback)(input, &mut out_buffer);
+            call_back(&mut self.callback, &mut self.marks, input, &mut out_buffer);
             let out_avail = out_buffer.len();
             copy_out_ring(out_avail, &mut sub_buffer, &mut out_buffer, outputs);
             self.tmp_state = Some((in_buffer, out_buffer, sub_buffer));
@@ -81,25 +187,39 @@ impl Node for Callback {
             self.base_mix.mix_inputs_ring(inputs, &mut in_buffer);
             let ref mut output = outputs[0];
             output.active = in_buffer.active;
-            (self.callback)(&mut in_buffer, output);
+            call_back(&mut self.callback, &mut self.marks, &mut in_buffer, output);
             self.tmp_state = Some((in_buffer, out_buffer, sub_buffer));
         }
         else {
             let (mut in_buffer, mut out_buffer, mut sub_buffer) = self.tmp_state.take().unwrap();
             self.base_mix.mix_inputs_ring(inputs, &mut in_buffer);
             out_buffer.active = in_buffer.active;
-            (self.callback)(&mut in_buffer, &mut out_buffer);
+            call_back(&mut self.callback, &mut self.marks, &mut in_buffer, &mut out_buffer);
             let out_avail = out_buffer.len();
             copy_out_ring(out_avail, &mut sub_buffer, &mut out_buffer, outputs);
             self.tmp_state = Some((in_buffer, out_buffer, sub_buffer));
         }
     }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("callback")
+    }
+
+    fn reset(&mut self) {
+        self._reset();
+    }
+
+    fn flush(&mut self) {
+        self._flush();
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::time::Instant;
+
     use super::Callback;
-    use super::super::{Node, RingBuffer};
+    use super::super::{Node, RingBuffer, TimeMark};
 
     #[test]
     fn it_calls_back() {
@@ -124,4 +244,48 @@ mod test {
         outputs[0].read_into(avail, &mut o1);
         assert_eq!(o1[0], 48);
     }
+
+    #[test]
+    fn it_carries_marks_through() {
+        // keeps every other sample, like a Rate from 96000 to 48000
+        let mut a = Callback::new(Box::new(|input, output| {
+            let avail = input.len();
+            let slice = input.read_slice(avail);
+            for (i, o) in output.write_slice(avail / 2).iter_mut().enumerate() {
+                *o = slice[i * 2];
+            }
+        }));
+        let mark = TimeMark::new("a", Instant::now());
+        let mut inputs = vec!(RingBuffer::new(), RingBuffer::new());
+        inputs[0].write_from(48, &vec!(0; 48));
+        inputs[0].mark(20, mark.clone());
+        inputs[1].write_from(48, &vec!(0; 48));
+        let mut outputs = vec!(RingBuffer::new(), RingBuffer::new());
+        {
+            let n = &mut a as &mut Node;
+            n.update(&mut inputs, &mut outputs);
+        }
+        let mut marks = Vec::new();
+        outputs[1].peek_marks(24, &mut marks);
+        assert_eq!(marks, vec!((10, mark)));
+    }
+
+    #[test]
+    fn it_resets_callbacks() {
+        // Writes how many times it has been called.
+        let mut a = Callback::resettable(Box::new(|| {
+            let mut calls = 0;
+            Box::new(move |_, output| {
+                calls += 1;
+                output.write_from(1, &vec!(calls));
+            })
+        }));
+        let mut inputs = vec!(RingBuffer::new());
+        let mut outputs = vec!(RingBuffer::new());
+        a.update(&mut inputs, &mut outputs);
+        a.update(&mut inputs, &mut outputs);
+        a.reset();
+        a.update(&mut inputs, &mut outputs);
+        assert_eq!(outputs[0].read_slice(3).iter().cloned().collect::<Vec<i16>>(), vec!(1, 2, 1));
+    }
 }
@@ -1,25 +1,96 @@
-use super::{Node, RingBuffer, copy_out_ring};
+use std::any::Any;
+use std::time::Duration;
 
-type CaptureFn = Box<FnMut(&mut RingBuffer)>;
+use super::{Node, NodeInfo, NodeStatus, RingBuffer, Sample, StreamFormat, copy_out_ring, is_graph_sample};
 
-pub struct Capture {
-    tmp_state: Option<(RingBuffer, Vec<i16>)>,
-    callback: CaptureFn,
+pub type CaptureFn<T = i16> = Box<FnMut(&mut RingBuffer<T>) + Send>;
+
+pub struct Capture<T: Sample = i16> {
+    tmp_state: Option<(RingBuffer<T>, Vec<i16>)>,
+    // the graph's samples when T isn't i16
+    converted: Option<RingBuffer>,
+    callback: CaptureFn<T>,
+    format: StreamFormat,
+    name: Option<String>,
+    latency: Option<Duration>,
+    status: NodeStatus,
 }
 
 impl Capture {
     pub fn new(callback: CaptureFn) -> Capture {
+        Capture::converting(callback)
+    }
+}
+
+impl<T: Sample> Capture<T> {
+    // A capture writing samples of type T, like 24 bit frames in an i32,
+    // converted to i16 for the graph.
+    pub fn converting(callback: CaptureFn<T>) -> Capture<T> {
         Capture {
             tmp_state: Some((RingBuffer::new(), Vec::<i16>::new())),
+            converted: if is_graph_sample::<T>() {None} else {Some(RingBuffer::new())},
             callback: callback,
+            format: StreamFormat::any(),
+            name: None,
+            latency: None,
+            status: NodeStatus::Idle,
+        }
+    }
+
+    // The format the device captures.
+    pub fn with_format(self, format: StreamFormat) -> Capture<T> {
+        Capture {
+            format: format,
+            ..self
+        }
+    }
+
+    // The name of the device it captures, like a card's.
+    pub fn with_name(self, name: &str) -> Capture<T> {
+        Capture {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    // How long the device takes to capture samples.
+    pub fn with_latency(self, latency: Duration) -> Capture<T> {
+        Capture {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    fn node_info(&self) -> NodeInfo {
+        NodeInfo {
+            kind: "capture",
+            name: self.name.clone(),
+            rate: self.format.rate,
+            channels: self.format.channels,
+            latency: self.latency,
+            status: self.status,
+        }
+    }
+
+    fn set_status(&mut self, active: bool) {
+        self.status = if active {NodeStatus::Running} else {NodeStatus::Inactive};
+    }
+
+    fn _flush(&mut self) {
+        if let Some((ref mut ring, _)) = self.tmp_state {
+            ring.clear();
+        }
+        if let Some(ref mut converted) = self.converted {
+            converted.clear();
         }
     }
 }
 
-impl Node for Capture {
-    fn update(&mut self, _: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+impl Capture {
+    fn update_samples(&mut self, outputs: &mut [RingBuffer]) {
         let (mut ring, mut buffer) = self.tmp_state.take().unwrap();
         (self.callback)(&mut ring);
+        self.set_status(ring.active);
         let avail = ring.len();
         // ring.read_into(avail, &mut buffer);
         // copy_out(avail, &buffer, outputs);
@@ -28,6 +99,37 @@ impl Node for Capture {
     }
 }
 
+impl<T: Sample> Node for Capture<T> {
+    fn update(&mut self, _: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+        if let Some(capture) = (self as &mut Any).downcast_mut::<Capture>() {
+            return capture.update_samples(outputs);
+        }
+        let (mut ring, mut buffer) = self.tmp_state.take().unwrap();
+        let mut converted = self.converted.take().unwrap();
+        (self.callback)(&mut ring);
+        self.set_status(ring.active);
+        converted.active = ring.active;
+        let avail = ring.len();
+        converted.write_converted_from_ring(avail, &mut ring);
+        let avail = converted.len();
+        copy_out_ring(avail, &mut buffer, &mut converted, outputs);
+        self.converted = Some(converted);
+        self.tmp_state = Some((ring, buffer));
+    }
+
+    fn output_format(&self, _: StreamFormat) -> StreamFormat {
+        self.format
+    }
+
+    fn info(&self) -> NodeInfo {
+        self.node_info()
+    }
+
+    fn flush(&mut self) {
+        self._flush();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::Capture;
@@ -52,4 +154,20 @@ mod test {
         outputs[0].read_into(avail, &mut o1);
         assert_eq!(o1[0], 48);
     }
+
+    #[test]
+    fn it_captures_wider_samples() {
+        // 24 bit samples in the top of an i32
+        let mut a = Capture::<i32>::converting(Box::new(|ring| {
+            ring.write_from(2, &vec!(0x123456 << 8, -0x400000 << 8));
+        }));
+        let mut outputs = vec!(RingBuffer::new());
+        {
+            let n = &mut a as &mut Node;
+            n.update(&mut vec!(), &mut outputs);
+        }
+        let mut o1 = Vec::<i16>::new();
+        outputs[0].read_into(2, &mut o1);
+        assert_eq!(o1, vec!(0x1234, -0x4000));
+    }
 }
@@ -0,0 +1,68 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Where nodes read the time. A simulated clock only moves when advanced, so
+// an offline render behaves the same every run.
+#[derive(Clone)]
+pub struct Clock {
+    start: Instant,
+    simulated: Option<Arc<Mutex<Duration>>>,
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::system()
+    }
+}
+
+impl Clock {
+    pub fn system() -> Clock {
+        Clock {
+            start: Instant::now(),
+            simulated: None,
+        }
+    }
+
+    pub fn simulated() -> Clock {
+        Clock {
+            start: Instant::now(),
+            simulated: Some(Arc::new(Mutex::new(Duration::new(0, 0)))),
+        }
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        self.simulated.is_some()
+    }
+
+    pub fn now(&self) -> Instant {
+        match self.simulated {
+            Some(ref elapsed) => self.start + *elapsed.lock().unwrap(),
+            None => Instant::now(),
+        }
+    }
+
+    // Move a simulated clock and every clone of it forward. Does nothing to a
+    // system clock.
+    pub fn advance(&self, duration: Duration) {
+        if let Some(ref elapsed) = self.simulated {
+            let mut elapsed = elapsed.lock().unwrap();
+            *elapsed = *elapsed + duration;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::Clock;
+
+    #[test]
+    fn it_advances_simulated_time() {
+        let clock = Clock::simulated();
+        let start = clock.now();
+        assert_eq!(clock.now(), start);
+        clock.clone().advance(Duration::from_millis(1500));
+        assert_eq!(clock.now().duration_since(start), Duration::from_millis(1500));
+    }
+}
@@ -1,13 +1,13 @@
-use super::{RingBuffer};
+use super::{RingBuffer, Sample};
 
-pub fn copy_out(amount: usize, buffer: &Vec<i16>, outputs: &mut [RingBuffer]) {
+pub fn copy_out<T: Sample>(amount: usize, buffer: &Vec<T>, outputs: &mut [RingBuffer<T>]) {
     for out in outputs.iter_mut() {
         out.write_from(amount, buffer);
         // print!("{:?} ", out.buffer.as_ptr());
     }
 }
 
-pub fn copy_out_ring(amount: usize, buffer: &mut Vec<i16>, ring: &mut RingBuffer, outputs: &mut [RingBuffer]) {
+pub fn copy_out_ring<T: Sample>(amount: usize, buffer: &mut Vec<T>, ring: &mut RingBuffer<T>, outputs: &mut [RingBuffer<T>]) {
     // let num_outputs = outputs.iter().filter(|x| x.active).count();
     // if num_outputs == 0 {
     //     return;
@@ -21,6 +21,9 @@ pub fn copy_out_ring(amount: usize, buffer: &mut Vec<i16>, ring: &mut RingBuffer
     // else {
         // ring.read_into(amount, buffer);
         let active = ring.active;
+        for out in outputs.iter_mut() {
+            ring.forward_marks(amount, out);
+        }
         let slice = ring.read_slice(amount);
         for out in outputs.iter_mut() {
             out.active = active;
@@ -0,0 +1,85 @@
+use std::fmt;
+
+// The sample rate and channel count of the samples on an edge. A None field
+// is not known yet, or, for a node's input, accepts whatever arrives.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StreamFormat {
+    pub rate: Option<usize>,
+    pub channels: Option<usize>,
+}
+
+fn merge_field(a: Option<usize>, b: Option<usize>) -> Result<Option<usize>, ()> {
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => Err(()),
+        (Some(a), _) => Ok(Some(a)),
+        (None, b) => Ok(b),
+    }
+}
+
+impl StreamFormat {
+    pub fn new(rate: usize, channels: usize) -> StreamFormat {
+        StreamFormat {
+            rate: Some(rate),
+            channels: Some(channels),
+        }
+    }
+
+    pub fn any() -> StreamFormat {
+        Default::default()
+    }
+
+    pub fn with_rate(self, rate: usize) -> StreamFormat {
+        StreamFormat {
+            rate: Some(rate),
+            ..self
+        }
+    }
+
+    pub fn with_channels(self, channels: usize) -> StreamFormat {
+        StreamFormat {
+            channels: Some(channels),
+            ..self
+        }
+    }
+
+    // The format agreeing with both, or None if they disagree on a field
+    // both of them know.
+    pub fn merge(&self, other: &StreamFormat) -> Option<StreamFormat> {
+        match (merge_field(self.rate, other.rate), merge_field(self.channels, other.channels)) {
+            (Ok(rate), Ok(channels)) => Some(StreamFormat {
+                rate: rate,
+                channels: channels,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StreamFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.rate {
+            Some(rate) => try!(write!(f, "{} Hz", rate)),
+            None => try!(write!(f, "any rate")),
+        }
+        match self.channels {
+            Some(1) => write!(f, ", 1 channel"),
+            Some(channels) => write!(f, ", {} channels", channels),
+            None => write!(f, ", any channels"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::StreamFormat;
+
+    #[test]
+    fn it_merges_formats() {
+        let stereo = StreamFormat::any().with_channels(2);
+        assert_eq!(stereo.merge(&StreamFormat::new(44100, 2)), Some(StreamFormat::new(44100, 2)));
+        assert_eq!(stereo.merge(&StreamFormat::any()), Some(stereo));
+        assert_eq!(stereo.merge(&StreamFormat::new(48000, 1)), None);
+        assert_eq!(format!("{}", StreamFormat::new(48000, 1)), "48000 Hz, 1 channel");
+        assert_eq!(format!("{}", stereo), "any rate, 2 channels");
+    }
+}
@@ -1,7 +1,145 @@
+use std::cmp::min;
+use std::slice::{Chunks, ChunksMut};
 
-struct Frame {
-    periodSize: usize,
+use super::{RingBuffer, Sample};
+
+// Interleaved samples held as whole frames, one sample per channel each.
+pub struct Frame<T: Sample = i16> {
     channels: usize,
-    byteWidth: usize,
-    buffer: Vec<u8>,
+    samples: Vec<T>,
+}
+
+// One channel's samples across a Frame's frames.
+pub struct ChannelIter<'a, T: 'a> {
+    frames: Chunks<'a, T>,
+    channel: usize,
+}
+
+pub struct ChannelIterMut<'a, T: 'a> {
+    frames: ChunksMut<'a, T>,
+    channel: usize,
+}
+
+impl<T: Sample> Frame<T> {
+    pub fn new(channels: usize) -> Frame<T> {
+        assert!(channels > 0, "frames need at least one channel");
+        Frame {
+            channels: channels,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    // The number of frames held.
+    pub fn len(&self) -> usize {
+        self.samples.len() / self.channels
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    // The interleaved samples of every frame.
+    pub fn samples(&self) -> &[T] {
+        &self.samples
+    }
+
+    pub fn frame(&self, index: usize) -> &[T] {
+        &self.samples[index * self.channels..(index + 1) * self.channels]
+    }
+
+    pub fn frame_mut(&mut self, index: usize) -> &mut [T] {
+        &mut self.samples[index * self.channels..(index + 1) * self.channels]
+    }
+
+    pub fn frames(&self) -> Chunks<T> {
+        self.samples.chunks(self.channels)
+    }
+
+    pub fn frames_mut(&mut self) -> ChunksMut<T> {
+        self.samples.chunks_mut(self.channels)
+    }
+
+    pub fn channel(&self, channel: usize) -> ChannelIter<T> {
+        assert!(channel < self.channels);
+        ChannelIter {
+            frames: self.samples.chunks(self.channels),
+            channel: channel,
+        }
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> ChannelIterMut<T> {
+        assert!(channel < self.channels);
+        ChannelIterMut {
+            frames: self.samples.chunks_mut(self.channels),
+            channel: channel,
+        }
+    }
+
+    // Add a frame of silence and return it.
+    pub fn push_frame(&mut self) -> &mut [T] {
+        let index = self.len();
+        for _ in 0..self.channels {
+            self.samples.push(T::default());
+        }
+        self.frame_mut(index)
+    }
+
+    // Replace what this holds with up to frames whole frames read from ring.
+    // A partial frame at the end of ring is left for the next read.
+    pub fn read_from(&mut self, ring: &mut RingBuffer<T>, frames: usize) -> usize {
+        let frames = min(frames, ring.frames(self.channels));
+        self.samples.clear();
+        self.samples.extend(ring.read_slice(frames * self.channels).iter().cloned());
+        frames
+    }
+
+    // Write every frame held into ring, returning how many frames fit.
+    pub fn write_to(&self, ring: &mut RingBuffer<T>) -> usize {
+        ring.write_from(self.samples.len(), &self.samples) / self.channels
+    }
+}
+
+impl<'a, T> Iterator for ChannelIter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        let channel = self.channel;
+        self.frames.next().map(|frame| &frame[channel])
+    }
+}
+
+impl<'a, T> Iterator for ChannelIterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<&'a mut T> {
+        let channel = self.channel;
+        self.frames.next().map(|frame| &mut frame[channel])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Frame;
+    use super::super::RingBuffer;
+
+    #[test]
+    fn it_reads_whole_frames() {
+        let mut ring = RingBuffer::from((0..8).collect::<Vec<i16>>());
+        let mut frame = Frame::new(3);
+        assert_eq!(frame.read_from(&mut ring, 4), 2);
+        assert_eq!(ring.len(), 2);
+        assert_eq!(frame.frame(1), &[3, 4, 5]);
+        assert_eq!(frame.channel(2).cloned().collect::<Vec<i16>>(), vec!(2, 5));
+
+        for sample in frame.channel_mut(0) {
+            *sample = -1;
+        }
+        frame.push_frame()[1] = 9;
+        assert_eq!(frame.write_to(&mut ring), 3);
+        assert_eq!(ring.read_slice(11).iter().cloned().collect::<Vec<i16>>(), vec!(6, 7, -1, 1, 2, -1, 4, 5, 0, 9, 0));
+    }
 }
@@ -1,24 +1,222 @@
 // use std::collections::BTreeMap;
+use std::fmt;
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
 
-use super::{Node, RingBuffer};
+use super::{Node, NodeInfo, NodeStatus, IntoBoxNode, BaseMix, RingBuffer, port_inputs, ReadCursor, OverflowPolicy, StreamFormat};
+
+#[derive(Debug, PartialEq)]
+pub enum GraphError {
+    NoSuchNode(usize),
+    NoSuchEdge(usize, usize),
+    EdgeExists(usize, usize),
+    Cycle(usize, usize),
+    NoSuchPort(usize, &'static str),
+    // (node id, the format it writes, node id, the format it reads)
+    FormatMismatch(usize, StreamFormat, usize, StreamFormat),
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GraphError::NoSuchNode(id) => write!(f, "no node {}", id),
+            GraphError::NoSuchEdge(id, output_id) => write!(f, "no edge from node {} to node {}", id, output_id),
+            GraphError::EdgeExists(id, output_id) => write!(f, "node {} already has that edge to node {}", id, output_id),
+            GraphError::Cycle(id, output_id) => write!(f, "an edge from node {} to node {} makes a cycle", id, output_id),
+            GraphError::NoSuchPort(id, port) => write!(f, "node {} has no port {:?}", id, port),
+            GraphError::FormatMismatch(id, output_format, output_id, input_format) =>
+                write!(f, "node {} writes {} but node {} reads {}", id, output_format, output_id, input_format),
+        }
+    }
+}
+
+macro_rules! try_node {
+    ($graph:expr, $id:expr) => {
+        match $graph.nodes.get($id) {
+            Some(&Some(_)) => {},
+            _ => return Err(GraphError::NoSuchNode($id)),
+        }
+    }
+}
+
+// The id of a connected node that remembers the node's type, so
+// Graph::get and get_mut can hand back the node itself.
+pub struct NodeId<T: ?Sized> {
+    id: usize,
+    node_type: PhantomData<Box<T>>,
+}
+
+impl<T: ?Sized> NodeId<T> {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl<T: ?Sized> Clone for NodeId<T> {
+    fn clone(&self) -> NodeId<T> {
+        *self
+    }
+}
+
+impl<T: ?Sized> Copy for NodeId<T> {}
+
+impl<T: ?Sized> PartialEq for NodeId<T> {
+    fn eq(&self, other: &NodeId<T>) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T: ?Sized> fmt::Debug for NodeId<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "NodeId({})", self.id)
+    }
+}
+
+impl<T: ?Sized> From<NodeId<T>> for usize {
+    fn from(id: NodeId<T>) -> usize {
+        id.id
+    }
+}
 
 pub struct GraphNodeParams {
     pub to: Vec<usize>,
+    pub from: Vec<usize>,
+    // (this node's output port, node id, that node's input port)
+    pub to_ports: Vec<(&'static str, usize, &'static str)>,
+    // (node id, that node's output port, this node's input port)
+    pub from_ports: Vec<(usize, &'static str, &'static str)>,
+    // feedback edges, read an update after they are written
+    pub feedback_to: Vec<usize>,
+    pub feedback_from: Vec<usize>,
+    // (node id, policy) for the edges connected between this node and that
+    // one, other edges drop their oldest samples
+    pub overflow: Vec<(usize, OverflowPolicy)>,
 }
 
 impl Default for GraphNodeParams {
     fn default() -> GraphNodeParams {
         GraphNodeParams {
             to: vec!(),
+            from: vec!(),
+            to_ports: vec!(),
+            from_ports: vec!(),
+            feedback_to: vec!(),
+            feedback_from: vec!(),
+            overflow: vec!(),
+        }
+    }
+}
+
+impl GraphNodeParams {
+    // Add an edge to id, a NodeId or its usize.
+    pub fn to<I: Into<usize>>(mut self, id: I) -> GraphNodeParams {
+        self.to.push(id.into());
+        self
+    }
+
+    // Add an edge from id, a NodeId or its usize.
+    pub fn from<I: Into<usize>>(mut self, id: I) -> GraphNodeParams {
+        self.from.push(id.into());
+        self
+    }
+}
+
+// The state of an edge's buffer between updates.
+#[derive(Debug, PartialEq)]
+pub struct EdgeState {
+    pub from: usize,
+    pub from_port: &'static str,
+    pub to: usize,
+    pub to_port: &'static str,
+    pub len: usize,
+    pub active: bool,
+    pub feedback: bool,
+    pub overflow: OverflowPolicy,
+    pub overruns: usize,
+    pub underruns: usize,
+}
+
+pub const PROFILE_BUCKETS: usize = 16;
+
+// Update times for one node. Histogram buckets start below 1us and double in
+// width, [0, 1us), [1us, 2us), [2us, 4us) .. with the last bucket holding
+// everything 16ms or longer.
+#[derive(Clone, Debug, Default)]
+pub struct NodeProfile {
+    pub last_ns: u64,
+    pub max_ns: u64,
+    pub total_ns: u64,
+    pub updates: u64,
+    pub histogram: [u64; PROFILE_BUCKETS],
+}
+
+impl NodeProfile {
+    pub fn average_ns(&self) -> u64 {
+        if self.updates > 0 {
+            self.total_ns / self.updates
+        }
+        else {
+            0
+        }
+    }
+
+    fn record(&mut self, ns: u64) {
+        self.last_ns = ns;
+        if ns > self.max_ns {
+            self.max_ns = ns;
+        }
+        self.total_ns += ns;
+        self.updates += 1;
+
+        let mut bucket = 0;
+        let mut us = ns / 1000;
+        while us > 0 && bucket < PROFILE_BUCKETS - 1 {
+            us >>= 1;
+            bucket += 1;
         }
+        self.histogram[bucket] += 1;
+    }
+}
+
+type BufferCache = Vec<Option<Vec<Option<RingBuffer>>>>;
+// (output port, the edge's place in the port's ring) for each edge sharing its
+// port's ring
+type CursorCache = Vec<Option<Vec<Option<(usize, ReadCursor)>>>>;
+
+// Whether output_index is the first of the edges sharing its port's ring.
+fn first_shared(cursors: &[Option<(usize, ReadCursor)>], output_index: usize) -> bool {
+    match cursors[output_index] {
+        Some((port, _)) => !cursors[..output_index].iter().any(|cursor| cursor.map_or(false, |(other, _)| other == port)),
+        None => false,
     }
 }
 
+// How one of a node's outputs in params.to connects.
+#[derive(Clone, Copy, PartialEq)]
+struct OutputEdge {
+    port: usize,
+    input_port: usize,
+    // A feedback edge's output node reads what was written an update ago.
+    // Feedback edges don't order updates, so they may close a cycle.
+    feedback: bool,
+}
+
 struct GraphNode {
     id: usize,
     params: GraphNodeParams,
     node: Box<Node>,
-    input_ids: Vec<(usize, usize)>
+    // how each output in params.to connects
+    output_edges: Vec<OutputEdge>,
+    // (input node, output index) sorted by input port
+    input_ids: Vec<(usize, usize)>,
+    profile: NodeProfile,
+    // Whether the node's main inputs are passed straight to its outputs
+    // instead of updating it.
+    bypassed: bool,
+    bypass_mix: BaseMix,
     // inputs: Vec<RingBuffer>,
     // outputs: Vec<usize>,
 }
@@ -29,19 +227,186 @@ impl GraphNode {
             id: id,
             params: params,
             node: node,
+            output_edges: Vec::new(),
             input_ids: Vec::new(),
+            profile: Default::default(),
+            bypassed: false,
+            bypass_mix: BaseMix::new(),
             // inputs: Vec::new(),
             // outputs: params.to.iter().collect(),
         }
     }
+
+    fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer], profiling: bool) {
+        if self.bypassed {
+            // Drop what other ports were sent.
+            for input in inputs.iter_mut().filter(|input| input.to_port != 0) {
+                let avail = input.len();
+                input.read_slice(avail);
+            }
+            self.bypass_mix.update(port_inputs(inputs, 0), outputs);
+        }
+        else if profiling {
+            let start = Instant::now();
+            self.node.update(inputs, outputs);
+            let elapsed = start.elapsed();
+            self.profile.record(elapsed.as_secs() * 1000000000 + elapsed.subsec_nanos() as u64);
+        }
+        else {
+            self.node.update(inputs, outputs);
+        }
+    }
+
+    // Move this node's buffers out of the cache for its update. Feedback
+    // outputs are written to their own buffer and carried over to the one
+    // read in outputs_cache after the update. Edges sharing a port's ring in
+    // shared_cache hand it to the node once, and each reader reads a view of
+    // it from its own cursor, so readers of a ring can update at once.
+    fn take_buffers(&self, outputs_cache: &mut BufferCache, feedback_cache: &mut BufferCache, shared_cache: &mut BufferCache, cursor_cache: &mut CursorCache, inputs: &mut Vec<RingBuffer>, outputs: &mut Vec<RingBuffer>) {
+        for &(output_id, output_index) in self.input_ids.iter() {
+            if let Some(ref mut cursors) = cursor_cache[output_id] {
+                if let Some((port, ref cursor)) = cursors[output_index] {
+                    inputs.push(shared_cache[output_id].as_ref().unwrap()[port].as_ref().unwrap().reader(cursor));
+                    continue;
+                }
+            }
+            if let Some(ref mut node_outputs) = outputs_cache[output_id] {
+                inputs.push(node_outputs[output_index].take().unwrap());
+            }
+        }
+        for (output_index, edge) in self.output_edges.iter().enumerate() {
+            if let Some(ref cursors) = cursor_cache[self.id] {
+                if let Some((port, _)) = cursors[output_index] {
+                    if first_shared(cursors, output_index) {
+                        // Write from the slowest reader so only what it hasn't
+                        // read yet holds the ring back.
+                        let slowest = cursors.iter()
+                        .filter_map(|&cursor| cursor)
+                        .filter(|&(other, _)| other == port)
+                        .map(|(_, cursor)| cursor.position)
+                        .min()
+                        .unwrap();
+                        let mut ring = shared_cache[self.id].as_mut().unwrap()[port].take().unwrap();
+                        ring.seek(slowest);
+                        outputs.push(ring);
+                    }
+                    continue;
+                }
+            }
+            let cache = if edge.feedback {&mut *feedback_cache} else {&mut *outputs_cache};
+            if let Some(ref mut node_outputs) = cache[self.id] {
+                outputs.push(node_outputs[output_index].take().unwrap());
+            }
+        }
+    }
+
+    fn restore_buffers(&self, outputs_cache: &mut BufferCache, feedback_cache: &mut BufferCache, shared_cache: &mut BufferCache, cursor_cache: &mut CursorCache, inputs: &mut Vec<RingBuffer>, outputs: &mut Vec<RingBuffer>) {
+        for (output_index, edge) in self.output_edges.iter().enumerate().rev() {
+            if let Some(ref mut cursors) = cursor_cache[self.id] {
+                if let Some((port, _)) = cursors[output_index] {
+                    if first_shared(cursors, output_index) {
+                        let ring = outputs.pop().unwrap();
+                        // Readers a write overflowed past lost those samples.
+                        let oldest = ring.read_position();
+                        for &mut (other, ref mut cursor) in cursors.iter_mut().filter_map(|cursor| cursor.as_mut()) {
+                            if other == port && cursor.position < oldest {
+                                cursor.position = oldest;
+                            }
+                        }
+                        shared_cache[self.id].as_mut().unwrap()[port] = Some(ring);
+                    }
+                    continue;
+                }
+            }
+            let cache = if edge.feedback {&mut *feedback_cache} else {&mut *outputs_cache};
+            if let Some(ref mut node_outputs) = cache[self.id] {
+                node_outputs[output_index] = Some(outputs.pop().unwrap());
+            }
+        }
+        for &(output_id, output_index) in self.input_ids.iter().rev() {
+            if let Some(ref mut cursors) = cursor_cache[output_id] {
+                if let Some((_, ref mut cursor)) = cursors[output_index] {
+                    cursor.follow(&inputs.pop().unwrap());
+                    continue;
+                }
+            }
+            if let Some(ref mut node_outputs) = outputs_cache[output_id] {
+                node_outputs[output_index] = Some(inputs.pop().unwrap());
+            }
+        }
+    }
+}
+
+// A node and its buffers on their way to or back from a worker thread.
+struct Job {
+    gnode: GraphNode,
+    inputs: Vec<RingBuffer>,
+    outputs: Vec<RingBuffer>,
+    profiling: bool,
+}
+
+struct Workers {
+    jobs: Option<Sender<Job>>,
+    done: Receiver<Job>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl Workers {
+    fn new(threads: usize) -> Workers {
+        let (jobs, jobs_receiver) = channel::<Job>();
+        let jobs_receiver = Arc::new(Mutex::new(jobs_receiver));
+        let (done_sender, done) = channel::<Job>();
+        let threads = (0..threads).map(|_| {
+            let jobs_receiver = jobs_receiver.clone();
+            let done_sender = done_sender.clone();
+            thread::spawn(move || {
+                loop {
+                    let mut job = match jobs_receiver.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    job.gnode.update(&mut job.inputs, &mut job.outputs, job.profiling);
+                    if done_sender.send(job).is_err() {
+                        break;
+                    }
+                }
+            })
+        }).collect();
+        Workers {
+            jobs: Some(jobs),
+            done: done,
+            threads: threads,
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        // Closing the job channel stops each thread.
+        self.jobs.take();
+        for thread in self.threads.drain(..) {
+            thread.join().unwrap();
+        }
+    }
 }
 
 pub struct Graph {
     next_id: usize,
-    nodes: Vec<GraphNode>,
+    nodes: Vec<Option<GraphNode>>,
     inputs: Option<Vec<RingBuffer>>,
     outputs: Option<Vec<RingBuffer>>,
-    outputs_cache: Option<Vec<Option<Vec<Option<RingBuffer>>>>>,
+    outputs_cache: Option<BufferCache>,
+    // buffers feedback edges are written to, Some only for feedback edges
+    feedback_cache: Option<BufferCache>,
+    // each output port's ring, read by the edges fanning out of it
+    shared_cache: Option<BufferCache>,
+    cursor_cache: Option<CursorCache>,
+    // node ids with every node after the nodes feeding it
+    order: Vec<usize>,
+    // node ids grouped so no node depends on another in its level
+    levels: Vec<Vec<usize>>,
+    profiling: bool,
+    workers: Option<Workers>,
     // inputs: Vec<usize>,
     // outputs: Vec<usize>,
     // node to list of outputs
@@ -50,6 +415,82 @@ pub struct Graph {
     // buffers: Map<usize, Map<usize, Vec<RingBuffer>>>,
 }
 
+// Hand what feedback edges were written this update to the nodes reading
+// them next update.
+fn carry_feedback(outputs_cache: &mut BufferCache, feedback_cache: &mut BufferCache) {
+    for (node_outputs, node_feedback) in outputs_cache.iter_mut().zip(feedback_cache.iter_mut()) {
+        if let (&mut Some(ref mut node_outputs), &mut Some(ref mut node_feedback)) = (node_outputs, node_feedback) {
+            for (output, feedback) in node_outputs.iter_mut().zip(node_feedback.iter_mut()) {
+                if let (&mut Some(ref mut output), &mut Some(ref mut feedback)) = (output, feedback) {
+                    output.active = feedback.active;
+                    let avail = feedback.len();
+                    output.write_from_ring(avail, feedback);
+                }
+            }
+        }
+    }
+}
+
+// A ring of an edge's own.
+fn edge_ring(edge: &OutputEdge) -> RingBuffer {
+    let mut ring = RingBuffer::new();
+    ring.from_port = edge.port;
+    ring.to_port = edge.input_port;
+    // Nothing has been fed back before the first update.
+    ring.active = !edge.feedback;
+    ring
+}
+
+// A ring of an edge's own holding what it hadn't read yet from the shared
+// ring.
+fn unshare(shared: &mut RingBuffer, cursor: &mut ReadCursor, edge: &OutputEdge) -> RingBuffer {
+    let mut ring = edge_ring(edge);
+    shared.swap_cursor(cursor);
+    let avail = shared.len();
+    ring.write_from_ring(avail, shared);
+    ring.active = shared.active;
+    ring.overruns = shared.overruns;
+    ring.underruns = shared.underruns;
+    shared.swap_cursor(cursor);
+    ring
+}
+
+// A value in to_json, or null.
+fn json_option<T: fmt::Display>(value: Option<T>) -> String {
+    value.map_or(String::from("null"), |value| value.to_string())
+}
+
+// A JSON string literal; Debug quoting is Rust's, which escapes unicode and
+// control characters in ways JSON doesn't read.
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// Escapes a graphviz quoted label.
+fn dot_string(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn port_index(id: usize, ports: &[&'static str], port: &'static str) -> Result<usize, GraphError> {
+    match ports.iter().position(|&name| name == port) {
+        Some(index) => Ok(index),
+        None => Err(GraphError::NoSuchPort(id, port)),
+    }
+}
+
 impl Graph {
     pub fn new() -> Graph {
         Graph {
@@ -58,127 +499,1475 @@ impl Graph {
             inputs: Some(Vec::new()),
             outputs: Some(Vec::new()),
             outputs_cache: Some(Vec::new()),
+            feedback_cache: Some(Vec::new()),
+            shared_cache: Some(Vec::new()),
+            cursor_cache: Some(Vec::new()),
+            order: Vec::new(),
+            levels: Vec::new(),
+            profiling: false,
+            workers: None,
         }
     }
 
     // fn add_output(&mut self, node: Box<Node>) {}
-    pub fn connect(&mut self, node: Box<Node>, params: GraphNodeParams) -> usize {
-        let gnode = GraphNode::new(self.next_id, params, node);
-        self.next_id += 1;
+    pub fn connect<T: ?Sized + IntoBoxNode>(&mut self, node: Box<T>, params: GraphNodeParams) -> NodeId<T> {
+        match self.try_connect(node, params) {
+            Ok(id) => id,
+            Err(err) => panic!("connect: {}", err),
+        }
+    }
 
-        let mut outputs = Vec::new();
+    pub fn try_connect<T: ?Sized + IntoBoxNode>(&mut self, node: Box<T>, mut params: GraphNodeParams) -> Result<NodeId<T>, GraphError> {
+        let node = node.into_box_node();
+        let id = self.next_id;
 
-        for &output_id in gnode.params.to.iter() {
-            let output_index = outputs.len();
-            outputs.push(Some(RingBuffer::new()));
-            self.nodes[output_id].input_ids.push((gnode.id, output_index));
-            println!("{:?} {:?}", output_id, self.nodes[output_id].input_ids);
+        // Resolve every edge to (input id, output port, output id, input port).
+        let mut from = Vec::new();
+        for &input_id in params.from.iter() {
+            try_node!(self, input_id);
+            from.push((input_id, 0, id, 0));
+        }
+        for &(input_id, output_port, input_port) in params.from_ports.iter() {
+            try_node!(self, input_id);
+            let output_index = try!(self.output_port_index(input_id, output_port));
+            let input_index = try!(port_index(id, node.input_ports(), input_port));
+            from.push((input_id, output_index, id, input_index));
+        }
+        let mut to = Vec::new();
+        for &output_id in params.to.iter() {
+            try_node!(self, output_id);
+            to.push((id, 0, output_id, 0));
+        }
+        for &(output_port, output_id, input_port) in params.to_ports.iter() {
+            try_node!(self, output_id);
+            let output_index = try!(port_index(id, node.output_ports(), output_port));
+            let input_index = try!(self.input_port_index(output_id, input_port));
+            to.push((id, output_index, output_id, input_index));
+        }
+        let mut feedback = Vec::new();
+        for &input_id in params.feedback_from.iter() {
+            try_node!(self, input_id);
+            feedback.push((input_id, 0, id, 0));
+        }
+        for &output_id in params.feedback_to.iter() {
+            try_node!(self, output_id);
+            feedback.push((id, 0, output_id, 0));
+        }
+
+        for &(other_id, _) in params.overflow.iter() {
+            let connected = from.iter().chain(to.iter()).chain(feedback.iter())
+            .any(|&(input_id, _, output_id, _)| input_id == other_id || output_id == other_id);
+            if !connected {
+                return Err(GraphError::NoSuchEdge(id, other_id));
+            }
+        }
+
+        for &(_, _, output_id, _) in to.iter() {
+            for &(input_id, _, _, _) in from.iter() {
+                if output_id == input_id || self.reaches(output_id, input_id) {
+                    return Err(GraphError::Cycle(input_id, output_id));
+                }
+            }
         }
 
+        params.to.clear();
+        params.from.clear();
+        params.to_ports.clear();
+        params.from_ports.clear();
+        params.feedback_to.clear();
+        params.feedback_from.clear();
+        let overflow = params.overflow.drain(..).collect::<Vec<(usize, OverflowPolicy)>>();
+        let gnode = GraphNode::new(id, params, node);
+        self.next_id += 1;
+
         if let Some(ref mut outputs_cache) = self.outputs_cache {
-            outputs_cache.push(Some(outputs));
+            outputs_cache.push(Some(Vec::new()));
+        }
+        if let Some(ref mut feedback_cache) = self.feedback_cache {
+            feedback_cache.push(Some(Vec::new()));
+        }
+        if let Some(ref mut shared_cache) = self.shared_cache {
+            shared_cache.push(Some(Vec::new()));
+        }
+        if let Some(ref mut cursor_cache) = self.cursor_cache {
+            cursor_cache.push(Some(Vec::new()));
+        }
+
+        self.nodes.push(Some(gnode));
+
+        for &(input_id, output_port, output_id, input_port) in from.iter().chain(to.iter()) {
+            self.add_output(input_id, output_port, output_id, input_port, false);
+        }
+        for &(input_id, output_port, output_id, input_port) in feedback.iter() {
+            self.add_output(input_id, output_port, output_id, input_port, true);
+        }
+        self.sort();
+
+        if let Err(err) = self.formats() {
+            // Take the node back out, leaving the graph as it was.
+            let _ = self.take_node(id);
+            self.nodes.pop();
+            if let Some(ref mut outputs_cache) = self.outputs_cache {
+                outputs_cache.pop();
+            }
+            if let Some(ref mut feedback_cache) = self.feedback_cache {
+                feedback_cache.pop();
+            }
+            if let Some(ref mut shared_cache) = self.shared_cache {
+                shared_cache.pop();
+            }
+            if let Some(ref mut cursor_cache) = self.cursor_cache {
+                cursor_cache.pop();
+            }
+            self.next_id -= 1;
+            return Err(err);
         }
 
-        self.nodes.push(gnode);
+        for &(other_id, policy) in overflow.iter() {
+            self.set_output_overflow(id, other_id, policy);
+            self.set_output_overflow(other_id, id, policy);
+        }
 
-        self.next_id - 1
+        self.set_input_formats();
+        self.graph_node_mut(id).node.start();
+        Ok(NodeId {
+            id: id,
+            node_type: PhantomData,
+        })
     }
 
-    // pub fn disconnect(&mut self, node: usize) -> Box<Node> {}
-    // fn iter_mut(&mut self) -> Iter<&mut Box<Node>> {}
-    // fn iter_inputs(&mut self) -> Iter<&mut Box<Node>> {}
-    // fn iter_outputs(&mut self) -> Iter<&mut Box<Node>> {}
-    // fn iter_node_inputs(&mut self, node_id: usize) -> Iter <&mut Box<Node>> {}
-    // fn wait(&mut self) {}
+    // Connect the main ports of two nodes.
+    pub fn add_edge<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_id: O) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        self.add_edge_ports(id, 0, output_id, 0, false)
+    }
 
-    pub fn borrow(&self, id: usize) -> &Node {
-        &*self.nodes[id].node
+    // Connect the main ports of two nodes so output_id reads what id wrote
+    // the update before. Feedback edges may close a cycle.
+    pub fn add_feedback_edge<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_id: O) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        self.add_edge_ports(id, 0, output_id, 0, true)
     }
 
-    pub fn update(&mut self) {
-        let mut inputs = self.inputs.take().unwrap();
-        let mut outputs = self.outputs.take().unwrap();
-        let mut outputs_cache = self.outputs_cache.take().unwrap();
+    pub fn add_port_edge<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_port: &'static str, output_id: O, input_port: &'static str) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        let output_index = try!(self.output_port_index(id, output_port));
+        let input_index = try!(self.input_port_index(output_id, input_port));
+        self.add_edge_ports(id, output_index, output_id, input_index, false)
+    }
 
-        // println!("");
-        for node in self.nodes.iter_mut().rev() {
-            // fetch input buffers from output cache
-            for &(ref output_id, ref output_index) in node.input_ids.iter() {
-                // print!("{:?} {:?} ", output_id, output_index);
-                if let Some(ref mut node_outputs) = outputs_cache[*output_id] {
-                    inputs.push(node_outputs[*output_index].take().unwrap());
-                }
+    fn add_edge_ports(&mut self, id: usize, output_port: usize, output_id: usize, input_port: usize, feedback: bool) -> Result<(), GraphError> {
+        {
+            let gnode = self.graph_node_mut(id);
+            let edge = OutputEdge {
+                port: output_port,
+                input_port: input_port,
+                feedback: feedback,
+            };
+            let exists = gnode.params.to.iter().zip(gnode.output_edges.iter())
+            .any(|(&to, &other)| to == output_id && other == edge);
+            if exists {
+                return Err(GraphError::EdgeExists(id, output_id));
             }
+        }
+        if !feedback && (id == output_id || self.reaches(output_id, id)) {
+            return Err(GraphError::Cycle(id, output_id));
+        }
+        let output_index = self.add_output(id, output_port, output_id, input_port, feedback);
+        self.sort();
+        if let Err(err) = self.formats() {
+            self.remove_output(id, output_index);
+            self.sort();
+            return Err(err);
+        }
+        self.set_input_formats();
+        Ok(())
+    }
 
-            // update node
-            let mut node_outputs = outputs_cache[node.id].take().unwrap();
-            for i in 0..node_outputs.len() {
-                // print!("{:?} ", i);
-                outputs.push(node_outputs[i].take().unwrap());
-                // print!("{:?} ", outputs[i].buffer.as_ptr());
-            }
-            node.node.update(&mut inputs, &mut outputs);
-            for i in (0..node_outputs.len()).rev() {
-                // print!("{:?} ", i);
-                node_outputs[i] = Some(outputs.pop().unwrap());
+    // Remove every edge from id to output_id.
+    pub fn remove_edge<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_id: O) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        let mut removed = false;
+        while let Some(output_index) = self.graph_node_mut(id).params.to.iter().position(|&to| to == output_id) {
+            self.remove_output(id, output_index);
+            removed = true;
+        }
+        if !removed {
+            return Err(GraphError::NoSuchEdge(id, output_id));
+        }
+        self.sort();
+        self.set_input_formats();
+        Ok(())
+    }
+
+    // Remove the edge from id's output_port to output_id's input_port, leaving
+    // any others between them.
+    pub fn remove_port_edge<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_port: &'static str, output_id: O, input_port: &'static str) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        let output_index = try!(self.output_port_index(id, output_port));
+        let input_index = try!(self.input_port_index(output_id, input_port));
+        self.remove_edge_ports(id, output_index, output_id, input_index, false)
+    }
+
+    // Remove the feedback edge from id to output_id, leaving any others
+    // between them.
+    pub fn remove_feedback_edge<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_id: O) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        self.remove_edge_ports(id, 0, output_id, 0, true)
+    }
+
+    fn remove_edge_ports(&mut self, id: usize, output_port: usize, output_id: usize, input_port: usize, feedback: bool) -> Result<(), GraphError> {
+        let edge = OutputEdge {
+            port: output_port,
+            input_port: input_port,
+            feedback: feedback,
+        };
+        let output_index = {
+            let gnode = self.graph_node_mut(id);
+            match gnode.params.to.iter().zip(gnode.output_edges.iter())
+            .position(|(&to, &other)| to == output_id && other == edge) {
+                Some(output_index) => output_index,
+                None => return Err(GraphError::NoSuchEdge(id, output_id)),
             }
-            outputs_cache[node.id] = Some(node_outputs);
+        };
+        self.remove_output(id, output_index);
+        self.sort();
+        self.set_input_formats();
+        Ok(())
+    }
+
+    // Set what the edges from id to output_id do with writes that don't fit.
+    pub fn set_overflow<I: Into<usize>, O: Into<usize>>(&mut self, id: I, output_id: O, policy: OverflowPolicy) -> Result<(), GraphError> {
+        let (id, output_id) = (id.into(), output_id.into());
+        try_node!(self, id);
+        try_node!(self, output_id);
+        if !self.set_output_overflow(id, output_id, policy) {
+            return Err(GraphError::NoSuchEdge(id, output_id));
+        }
+        Ok(())
+    }
 
-            // restore input buffers
-            for &(ref output_id, ref output_index) in node.input_ids.iter().rev() {
-                // print!("{:?} {:?} ", output_id, output_index);
-                if let Some(ref mut node_outputs) = outputs_cache[*output_id] {
-                    node_outputs[*output_index] = Some(inputs.pop().unwrap());
+    fn set_output_overflow(&mut self, id: usize, output_id: usize, policy: OverflowPolicy) -> bool {
+        let output_indices = self.graph_node_mut(id).params.to.iter().enumerate()
+        .filter(|&(_, &to)| to == output_id)
+        .map(|(output_index, _)| output_index)
+        .collect::<Vec<usize>>();
+        for cache in self.outputs_cache.iter_mut().chain(self.feedback_cache.iter_mut()) {
+            if let Some(ref mut node_outputs) = cache[id] {
+                for &output_index in output_indices.iter() {
+                    if let Some(ref mut ring) = node_outputs[output_index] {
+                        ring.overflow = policy;
+                    }
                 }
             }
         }
+        // Edges sharing a port's ring drop their oldest samples. Other policies
+        // need a ring of the edge's own.
+        if policy != OverflowPolicy::default() {
+            let edges = self.nodes[id].as_ref().unwrap().output_edges.clone();
+            if let (&mut Some(ref mut outputs_cache), &mut Some(ref mut shared_cache), &mut Some(ref mut cursor_cache)) = (&mut self.outputs_cache, &mut self.shared_cache, &mut self.cursor_cache) {
+                if let (&mut Some(ref mut node_outputs), &mut Some(ref mut node_shared), &mut Some(ref mut cursors)) = (&mut outputs_cache[id], &mut shared_cache[id], &mut cursor_cache[id]) {
+                    for &output_index in output_indices.iter() {
+                        if let Some((port, mut cursor)) = cursors[output_index].take() {
+                            let mut ring = unshare(node_shared[port].as_mut().unwrap(), &mut cursor, &edges[output_index]);
+                            ring.overflow = policy;
+                            node_outputs[output_index] = Some(ring);
+                        }
+                    }
+                }
+            }
+        }
+        output_indices.len() > 0
+    }
 
-        self.inputs = Some(inputs);
-        self.outputs = Some(outputs);
-        self.outputs_cache = Some(outputs_cache);
+    fn output_port_index(&self, id: usize, port: &'static str) -> Result<usize, GraphError> {
+        port_index(id, self.nodes[id].as_ref().unwrap().node.output_ports(), port)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::super::*;
-    // use super::*;
+    fn input_port_index(&self, id: usize, port: &'static str) -> Result<usize, GraphError> {
+        port_index(id, self.nodes[id].as_ref().unwrap().node.input_ports(), port)
+    }
 
-    #[test]
-    fn it_connects() {
-        let mut g = Graph::new();
-        let input = Box::new(BaseMix::new());
-        let output = Box::new(BaseMix::new());
-        let output_id = g.connect(output, GraphNodeParams {
-            ..Default::default()
-        });
-        g.connect(input, GraphNodeParams {
-            to: vec!(output_id),
-            ..Default::default()
-        });
+    pub fn disconnect<I: Into<usize>>(&mut self, id: I) -> Result<Box<Node>, GraphError> {
+        let gnode = try!(self.take_node(id.into()));
+        let mut node = gnode.node;
+        // A bypassed node was stopped when it was bypassed.
+        if !gnode.bypassed {
+            node.stop();
+        }
+        Ok(node)
     }
 
-    #[test]
-    fn it_updates() {
-        let mut g = Graph::new();
-        let input = {
-            Box::new(Capture::new(Box::new(|output| {
-                output.write_from(48, &mut (0i16..48i16).collect());
-            })))
-        };
-        // input.mix_inputs(&mut [RingBuffer::from((0i16..48i16).collect())]);
-        let output = Box::new(BaseMix::new());
-        let output_id = g.connect(output, GraphNodeParams {
-            ..Default::default()
-        });
-        g.connect(input, GraphNodeParams {
-            to: vec!(output_id),
-            ..Default::default()
-        });
-        g.update();
+    fn take_node(&mut self, id: usize) -> Result<GraphNode, GraphError> {
+        try_node!(self, id);
+        let mut gnode = self.nodes[id].take().unwrap();
+
+        // Remove edges into this node. Removing an output shifts the indices
+        // of the outputs after it, so remove the highest indices first.
+        gnode.input_ids.sort_by(|a, b| b.1.cmp(&a.1));
+        for &(input_id, output_index) in gnode.input_ids.iter() {
+            self.remove_output(input_id, output_index);
+        }
+
+        // Remove edges out of this node.
+        for &output_id in gnode.params.to.iter() {
+            if let Some(ref mut output_node) = self.nodes[output_id] {
+                output_node.input_ids.retain(|&(input_id, _)| input_id != id);
+            }
+        }
+
+        if let Some(ref mut outputs_cache) = self.outputs_cache {
+            outputs_cache[id] = None;
+        }
+        if let Some(ref mut feedback_cache) = self.feedback_cache {
+            feedback_cache[id] = None;
+        }
+        if let Some(ref mut shared_cache) = self.shared_cache {
+            shared_cache[id] = None;
+        }
+        if let Some(ref mut cursor_cache) = self.cursor_cache {
+            cursor_cache[id] = None;
+        }
+        self.sort();
+        self.set_input_formats();
+
+        Ok(gnode)
+    }
+
+    // Pass node id's main inputs straight to its outputs instead of updating
+    // it, or stop doing so. The node is stopped and flushed when bypassed and
+    // reset and started again when it isn't. Fails, changing nothing, if the
+    // nodes around it can't read what passes through.
+    pub fn set_bypass<I: Into<usize>>(&mut self, id: I, bypass: bool) -> Result<(), GraphError> {
+        let id = id.into();
+        try_node!(self, id);
+        if self.graph_node_mut(id).bypassed == bypass {
+            return Ok(());
+        }
+        self.graph_node_mut(id).bypassed = bypass;
+        if let Err(err) = self.formats() {
+            self.graph_node_mut(id).bypassed = !bypass;
+            return Err(err);
+        }
+        self.set_input_formats();
+        let node = &mut self.graph_node_mut(id).node;
+        if bypass {
+            node.stop();
+            node.flush();
+        }
+        else {
+            node.reset();
+            node.start();
+        }
+        Ok(())
+    }
+
+    pub fn is_bypassed<I: Into<usize>>(&self, id: I) -> bool {
+        let id = id.into();
+        self.nodes.get(id).map_or(false, |gnode| gnode.as_ref().map_or(false, |gnode| gnode.bypassed))
+    }
+
+    // Start the graph over, like after the device it plays to comes back.
+    // Every node is stopped, flushed, reset and started again, and every
+    // edge is emptied. Bypassed nodes are only flushed and reset.
+    pub fn restart(&mut self) {
+        for &id in self.order.iter() {
+            let gnode = self.nodes[id].as_mut().unwrap();
+            if !gnode.bypassed {
+                gnode.node.stop();
+            }
+            gnode.node.flush();
+        }
         {
-            let output = g.borrow(output_id).downcast_ref::<BaseMix>().unwrap();
-            assert_eq!(output.accum.len(), 48);
+            let caches = self.outputs_cache.iter_mut()
+            .chain(self.feedback_cache.iter_mut())
+            .chain(self.shared_cache.iter_mut());
+            for cache in caches {
+                for ring in cache.iter_mut()
+                .filter_map(|node_outputs| node_outputs.as_mut())
+                .flat_map(|node_outputs| node_outputs.iter_mut())
+                .filter_map(|ring| ring.as_mut()) {
+                    ring.clear();
+                }
+            }
+        }
+        // Readers of a shared ring start over from its end.
+        if let (&mut Some(ref mut cursor_cache), &Some(ref shared_cache)) = (&mut self.cursor_cache, &self.shared_cache) {
+            for (node_cursors, node_shared) in cursor_cache.iter_mut().zip(shared_cache.iter()) {
+                if let (&mut Some(ref mut node_cursors), &Some(ref node_shared)) = (node_cursors, node_shared) {
+                    for &mut (port, ref mut cursor) in node_cursors.iter_mut().filter_map(|cursor| cursor.as_mut()) {
+                        cursor.position = node_shared[port].as_ref().unwrap().written();
+                    }
+                }
+            }
         }
+        for &id in self.order.iter() {
+            let gnode = self.nodes[id].as_mut().unwrap();
+            gnode.node.reset();
+            if !gnode.bypassed {
+                gnode.node.start();
+            }
+        }
+    }
+
+    fn is_feedback(&self, &(input_id, output_index): &(usize, usize)) -> bool {
+        self.nodes[input_id].as_ref().unwrap().output_edges[output_index].feedback
+    }
+
+    // Whether following edges, other than feedback edges, from start
+    // eventually leads to target.
+    fn reaches(&self, start: usize, target: usize) -> bool {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = vec!(start);
+        while let Some(id) = stack.pop() {
+            if id == target {
+                return true;
+            }
+            if visited[id] {
+                continue;
+            }
+            visited[id] = true;
+            if let Some(ref gnode) = self.nodes[id] {
+                stack.extend(gnode.params.to.iter().zip(gnode.output_edges.iter())
+                .filter(|&(_, edge)| !edge.feedback)
+                .map(|(&output_id, _)| output_id));
+            }
+        }
+        false
+    }
+
+    // Order nodes so each node updates after every node feeding it, other
+    // than through feedback edges. Nodes with no ordering between them update
+    // latest connected first.
+    fn sort(&mut self) {
+        let mut in_degrees = vec![0; self.nodes.len()];
+        for gnode in self.nodes.iter().filter_map(|gnode| gnode.as_ref()) {
+            in_degrees[gnode.id] = gnode.input_ids.iter().filter(|edge| !self.is_feedback(edge)).count();
+        }
+
+        let mut ready = self.nodes.iter()
+        .filter_map(|gnode| gnode.as_ref())
+        .filter(|gnode| in_degrees[gnode.id] == 0)
+        .map(|gnode| gnode.id)
+        .collect::<Vec<usize>>();
+
+        self.order.clear();
+        while let Some(id) = ready.pop() {
+            self.order.push(id);
+            if let Some(ref gnode) = self.nodes[id] {
+                for (&output_id, edge) in gnode.params.to.iter().zip(gnode.output_edges.iter()) {
+                    if edge.feedback {
+                        continue;
+                    }
+                    in_degrees[output_id] -= 1;
+                    if in_degrees[output_id] == 0 {
+                        ready.push(output_id);
+                    }
+                }
+            }
+        }
+
+        // A node's level is one past the deepest node feeding it. Readers of
+        // a shared ring read their own views of it, so they can share a
+        // level.
+        let mut node_levels = vec![0; self.nodes.len()];
+        self.levels.clear();
+        for &id in self.order.iter() {
+            let gnode = self.nodes[id].as_ref().unwrap();
+            let level = gnode.input_ids.iter()
+            .filter(|edge| !self.is_feedback(edge))
+            .map(|&(input_id, _)| node_levels[input_id] + 1)
+            .max()
+            .unwrap_or(0);
+            node_levels[id] = level;
+            if self.levels.len() <= level {
+                self.levels.push(Vec::new());
+            }
+            self.levels[level].push(id);
+        }
+    }
+
+    // The format each node writes. See input_and_output_formats.
+    fn formats(&self) -> Result<Vec<StreamFormat>, GraphError> {
+        self.input_and_output_formats().map(|(_, formats)| formats)
+    }
+
+    // Tell each node the format it reads.
+    fn set_input_formats(&mut self) {
+        if let Ok((input_formats, _)) = self.input_and_output_formats() {
+            for gnode in self.nodes.iter_mut().filter_map(|gnode| gnode.as_mut()) {
+                gnode.node.set_input_format(input_formats[gnode.id]);
+            }
+        }
+    }
+
+    // The format each node reads and writes, found by handing what each node
+    // writes to the nodes it feeds in update order. Err on the first edge
+    // carrying a format its output node does not read.
+    fn input_and_output_formats(&self) -> Result<(Vec<StreamFormat>, Vec<StreamFormat>), GraphError> {
+        let mut input_formats = vec![StreamFormat::any(); self.nodes.len()];
+        let mut formats = vec![StreamFormat::any(); self.nodes.len()];
+        for &id in self.order.iter() {
+            let gnode = self.nodes[id].as_ref().unwrap();
+            // A bypassed node passes on whatever it reads.
+            let mut input_format = if gnode.bypassed {StreamFormat::any()} else {gnode.node.input_format()};
+            for &(input_id, _) in gnode.input_ids.iter().filter(|edge| !self.is_feedback(edge)) {
+                input_format = match input_format.merge(&formats[input_id]) {
+                    Some(format) => format,
+                    None => return Err(GraphError::FormatMismatch(input_id, formats[input_id], id, input_format)),
+                };
+            }
+            input_formats[id] = input_format;
+            formats[id] = if gnode.bypassed {input_format} else {gnode.node.output_format(input_format)};
+        }
+        // Feedback edges are written after they are read, so check them once
+        // every format is known.
+        for gnode in self.nodes.iter().filter_map(|gnode| gnode.as_ref()) {
+            for (&output_id, edge) in gnode.params.to.iter().zip(gnode.output_edges.iter()) {
+                if edge.feedback && input_formats[output_id].merge(&formats[gnode.id]).is_none() {
+                    return Err(GraphError::FormatMismatch(gnode.id, formats[gnode.id], output_id, input_formats[output_id]));
+                }
+            }
+        }
+        Ok((input_formats, formats))
+    }
+
+    fn graph_node_mut(&mut self, id: usize) -> &mut GraphNode {
+        self.nodes[id].as_mut().expect("no node with that id")
+    }
+
+    // The input port an input edge connects to.
+    fn input_port(&self, &(input_id, output_index): &(usize, usize)) -> usize {
+        self.nodes[input_id].as_ref().unwrap().output_edges[output_index].input_port
+    }
+
+    fn add_output(&mut self, id: usize, output_port: usize, output_id: usize, input_port: usize, feedback: bool) -> usize {
+        // Keep outputs sorted by port.
+        let output_index = {
+            let gnode = self.graph_node_mut(id);
+            let output_index = gnode.output_edges.iter().take_while(|edge| edge.port <= output_port).count();
+            gnode.params.to.insert(output_index, output_id);
+            gnode.output_edges.insert(output_index, OutputEdge {
+                port: output_port,
+                input_port: input_port,
+                feedback: feedback,
+            });
+            output_index
+        };
+        let edge = self.nodes[id].as_ref().unwrap().output_edges[output_index];
+        if let Some(ref mut feedback_cache) = self.feedback_cache {
+            if let Some(ref mut node_outputs) = feedback_cache[id] {
+                node_outputs.insert(output_index, if feedback {Some(edge_ring(&edge))} else {None});
+            }
+        }
+        if let Some(ref mut outputs_cache) = self.outputs_cache {
+            if let Some(ref mut node_outputs) = outputs_cache[id] {
+                node_outputs.insert(output_index, Some(edge_ring(&edge)));
+            }
+        }
+        if let Some(ref mut cursor_cache) = self.cursor_cache {
+            if let Some(ref mut cursors) = cursor_cache[id] {
+                cursors.insert(output_index, None);
+            }
+        }
+
+        // Edges after the new one now live one index later.
+        self.shift_output_indices(id, output_index, 1);
+
+        // Keep inputs sorted by port.
+        let input_index = {
+            let input_ids = &self.nodes[output_id].as_ref().unwrap().input_ids;
+            input_ids.iter().take_while(|edge| self.input_port(edge) <= input_port).count()
+        };
+        self.graph_node_mut(output_id).input_ids.insert(input_index, (id, output_index));
+        self.share_outputs(id);
+        output_index
+    }
+
+    fn remove_output(&mut self, id: usize, output_index: usize) {
+        let output_id = {
+            let gnode = self.graph_node_mut(id);
+            gnode.output_edges.remove(output_index);
+            gnode.params.to.remove(output_index)
+        };
+        if let Some(ref mut outputs_cache) = self.outputs_cache {
+            if let Some(ref mut node_outputs) = outputs_cache[id] {
+                node_outputs.remove(output_index);
+            }
+        }
+        if let Some(ref mut feedback_cache) = self.feedback_cache {
+            if let Some(ref mut node_outputs) = feedback_cache[id] {
+                node_outputs.remove(output_index);
+            }
+        }
+        if let Some(ref mut cursor_cache) = self.cursor_cache {
+            if let Some(ref mut cursors) = cursor_cache[id] {
+                cursors.remove(output_index);
+            }
+        }
+
+        if let Some(ref mut output_node) = self.nodes[output_id] {
+            if let Some(position) = output_node.input_ids.iter().position(|&edge| edge == (id, output_index)) {
+                output_node.input_ids.remove(position);
+            }
+        }
+
+        // Edges after the removed one now live one index earlier.
+        self.shift_output_indices(id, output_index + 1, -1);
+        self.share_outputs(id);
+    }
+
+    // Share each of id's output ports' rings between the edges fanning out of
+    // it. Feedback edges, edges with an overflow policy other than dropping
+    // their oldest samples, and edges to a node another edge from the port
+    // also reaches keep rings of their own.
+    fn share_outputs(&mut self, id: usize) {
+        let (edges, unique) = {
+            let gnode = self.nodes[id].as_ref().unwrap();
+            let unique = gnode.output_edges.iter().zip(gnode.params.to.iter())
+            .map(|(edge, &to)| !edge.feedback && gnode.output_edges.iter().zip(gnode.params.to.iter())
+                .filter(|&(other, &other_to)| !other.feedback && other.port == edge.port && other_to == to)
+                .count() == 1)
+            .collect::<Vec<bool>>();
+            (gnode.output_edges.clone(), unique)
+        };
+        if let (&mut Some(ref mut outputs_cache), &mut Some(ref mut shared_cache), &mut Some(ref mut cursor_cache)) = (&mut self.outputs_cache, &mut self.shared_cache, &mut self.cursor_cache) {
+            if let (&mut Some(ref mut node_outputs), &mut Some(ref mut node_shared), &mut Some(ref mut cursors)) = (&mut outputs_cache[id], &mut shared_cache[id], &mut cursor_cache[id]) {
+                for (output_index, (edge, &unique)) in edges.iter().zip(unique.iter()).enumerate() {
+                    let share = unique && node_outputs[output_index].as_ref()
+                    .map_or(true, |ring| ring.overflow == OverflowPolicy::default());
+                    if share && cursors[output_index].is_none() {
+                        // What the edge's own ring held is dropped.
+                        while node_shared.len() <= edge.port {
+                            node_shared.push(None);
+                        }
+                        if node_shared[edge.port].is_none() {
+                            let mut ring = RingBuffer::new();
+                            ring.from_port = edge.port;
+                            node_shared[edge.port] = Some(ring);
+                        }
+                        let cursor = node_shared[edge.port].as_ref().unwrap().cursor(edge.input_port);
+                        cursors[output_index] = Some((edge.port, cursor));
+                        node_outputs[output_index] = None;
+                    }
+                    else if !share && cursors[output_index].is_some() {
+                        let (port, mut cursor) = cursors[output_index].take().unwrap();
+                        node_outputs[output_index] = Some(unshare(node_shared[port].as_mut().unwrap(), &mut cursor, edge));
+                    }
+                }
+            }
+        }
+    }
+
+    // Move the input edges pointing at id's outputs from output_index on.
+    fn shift_output_indices(&mut self, id: usize, output_index: usize, shift: isize) {
+        let mut to = self.graph_node_mut(id).params.to.clone();
+        to.sort();
+        to.dedup();
+        for &output_id in to.iter() {
+            if let Some(ref mut output_node) = self.nodes[output_id] {
+                for edge in output_node.input_ids.iter_mut() {
+                    if edge.0 == id && edge.1 >= output_index {
+                        edge.1 = (edge.1 as isize + shift) as usize;
+                    }
+                }
+            }
+        }
+    }
+
+    // fn iter_mut(&mut self) -> Iter<&mut Box<Node>> {}
+    // fn iter_inputs(&mut self) -> Iter<&mut Box<Node>> {}
+    // fn iter_outputs(&mut self) -> Iter<&mut Box<Node>> {}
+    // fn iter_node_inputs(&mut self, node_id: usize) -> Iter <&mut Box<Node>> {}
+    // fn wait(&mut self) {}
+
+    pub fn borrow<I: Into<usize>>(&self, id: I) -> &Node {
+        &*self.nodes[id.into()].as_ref().expect("no node with that id").node
+    }
+
+    pub fn borrow_mut<I: Into<usize>>(&mut self, id: I) -> &mut Node {
+        &mut *self.nodes[id.into()].as_mut().expect("no node with that id").node
+    }
+
+    // The node connected as id, or None once it has been disconnected.
+    pub fn get<T: Node>(&self, id: NodeId<T>) -> Option<&T> {
+        match self.nodes.get(id.id) {
+            Some(&Some(ref gnode)) => gnode.node.downcast_ref::<T>(),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut<T: Node>(&mut self, id: NodeId<T>) -> Option<&mut T> {
+        match self.nodes.get_mut(id.id) {
+            Some(&mut Some(ref mut gnode)) => gnode.node.downcast_mut::<T>(),
+            _ => None,
+        }
+    }
+
+    // The sample rate and channels node id writes, as far as the nodes
+    // feeding it declare them.
+    pub fn format<I: Into<usize>>(&self, id: I) -> Option<StreamFormat> {
+        let id = id.into();
+        match self.nodes.get(id) {
+            Some(&Some(_)) => self.formats().ok().map(|formats| formats[id]),
+            _ => None,
+        }
+    }
+
+    // What node id is, with the format filled in from the nodes feeding it
+    // where the node doesn't know it.
+    pub fn info<I: Into<usize>>(&self, id: I) -> Option<NodeInfo> {
+        let id = id.into();
+        match self.nodes.get(id) {
+            Some(&Some(ref gnode)) => {
+                let mut info = gnode.node.info();
+                if gnode.bypassed {
+                    info.status = NodeStatus::Bypassed;
+                }
+                if let Some(format) = self.format(id) {
+                    info.rate = info.rate.or(format.rate);
+                    info.channels = info.channels.or(format.channels);
+                }
+                Some(info)
+            },
+            _ => None,
+        }
+    }
+
+    pub fn node_ids(&self) -> Vec<usize> {
+        self.nodes.iter()
+        .filter_map(|gnode| gnode.as_ref())
+        .map(|gnode| gnode.id)
+        .collect()
+    }
+
+    pub fn edge_states(&self) -> Vec<EdgeState> {
+        let mut edges = Vec::new();
+        for gnode in self.nodes.iter().filter_map(|gnode| gnode.as_ref()) {
+            for (output_index, &output_id) in gnode.params.to.iter().enumerate() {
+                let cursor = self.cursor_cache.as_ref()
+                .and_then(|cursor_cache| cursor_cache[gnode.id].as_ref())
+                .and_then(|cursors| cursors[output_index]);
+                let ring = match cursor {
+                    Some((port, _)) => self.shared_cache.as_ref()
+                    .and_then(|shared_cache| shared_cache[gnode.id].as_ref())
+                    .and_then(|node_shared| node_shared[port].as_ref()),
+                    None => self.outputs_cache.as_ref()
+                    .and_then(|outputs_cache| outputs_cache[gnode.id].as_ref())
+                    .and_then(|node_outputs| node_outputs[output_index].as_ref()),
+                };
+                let feedback_ring = self.feedback_cache.as_ref()
+                .and_then(|feedback_cache| feedback_cache[gnode.id].as_ref())
+                .and_then(|node_outputs| node_outputs[output_index].as_ref());
+                // A feedback edge can overflow writing or carrying its samples.
+                let mut counters = ring.iter().chain(feedback_ring.iter())
+                .fold((0, 0), |(overruns, underruns), ring| (overruns + ring.overruns, underruns + ring.underruns));
+                let mut len = ring.map_or(0, |ring| ring.len());
+                // A shared ring holds what its slowest reader hasn't read.
+                if let (Some((_, cursor)), Some(ring)) = (cursor, ring) {
+                    counters.1 = cursor.underruns;
+                    len = (ring.written() - cursor.position) as usize;
+                }
+                let edge = gnode.output_edges[output_index];
+                edges.push(EdgeState {
+                    from: gnode.id,
+                    from_port: gnode.node.output_ports()[edge.port],
+                    to: output_id,
+                    to_port: self.nodes[output_id].as_ref().unwrap().node.input_ports()[edge.input_port],
+                    feedback: edge.feedback,
+                    len: len,
+                    active: ring.map_or(false, |ring| ring.active),
+                    overflow: ring.map_or(Default::default(), |ring| ring.overflow),
+                    overruns: counters.0,
+                    underruns: counters.1,
+                });
+            }
+        }
+        edges
+    }
+
+    // Graphviz source for the graph. Nodes are labeled with their names or
+    // kinds, edges with how many samples they hold, inactive edges are dashed
+    // and feedback edges are bold.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph audio {\n");
+        for id in self.node_ids() {
+            let info = self.info(id).unwrap();
+            dot.push_str(&format!("    {} [label=\"{}\"];\n", id, dot_string(info.label())));
+        }
+        for edge in self.edge_states() {
+            dot.push_str(&format!("    {} -> {} [label=\"{}\"{}{}];\n",
+                edge.from, edge.to, edge.len,
+                if edge.active {""} else {" style=dashed"},
+                if edge.feedback {" penwidth=2 constraint=false"} else {""}));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        let nodes = self.node_ids().iter()
+        .map(|&id| {
+            let info = self.info(id).unwrap();
+            format!("{{\"id\":{},\"kind\":\"{}\",\"name\":{},\"channels\":{},\"rate\":{},\"latency_ms\":{},\"status\":\"{}\"}}",
+                id, info.kind,
+                json_option(info.name.map(|name| json_string(&name))),
+                json_option(info.channels),
+                json_option(info.rate),
+                json_option(info.latency.map(|latency| latency.as_secs() * 1000 + latency.subsec_nanos() as u64 / 1000000)),
+                info.status)
+        })
+        .collect::<Vec<String>>();
+        let edges = self.edge_states().iter()
+        .map(|edge| format!("{{\"from\":{},\"from_port\":\"{}\",\"to\":{},\"to_port\":\"{}\",\"len\":{},\"active\":{},\"feedback\":{},\"overruns\":{},\"underruns\":{}}}",
+            edge.from, edge.from_port, edge.to, edge.to_port, edge.len, edge.active, edge.feedback, edge.overruns, edge.underruns))
+        .collect::<Vec<String>>();
+        format!("{{\"nodes\":[{}],\"edges\":[{}]}}", nodes.join(","), edges.join(","))
+    }
+
+    // Time each node's update. Turning profiling on starts every node's
+    // profile over.
+    pub fn set_profiling(&mut self, profiling: bool) {
+        if profiling && !self.profiling {
+            for gnode in self.nodes.iter_mut().filter_map(|gnode| gnode.as_mut()) {
+                gnode.profile = Default::default();
+            }
+        }
+        self.profiling = profiling;
+    }
+
+    pub fn profile<I: Into<usize>>(&self, id: I) -> Option<&NodeProfile> {
+        let id = id.into();
+        match self.nodes.get(id) {
+            Some(&Some(ref gnode)) => Some(&gnode.profile),
+            _ => None,
+        }
+    }
+
+    // Update the nodes of each level on this many worker threads, waiting for
+    // a level to finish before starting the next. 0 updates every node on the
+    // calling thread.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.workers = None;
+        if threads > 0 {
+            self.workers = Some(Workers::new(threads));
+        }
+    }
+
+    pub fn update(&mut self) {
+        if self.workers.is_some() {
+            return self.update_parallel();
+        }
+
+        let mut inputs = self.inputs.take().unwrap();
+        let mut outputs = self.outputs.take().unwrap();
+        let mut outputs_cache = self.outputs_cache.take().unwrap();
+        let mut feedback_cache = self.feedback_cache.take().unwrap();
+        let mut shared_cache = self.shared_cache.take().unwrap();
+        let mut cursor_cache = self.cursor_cache.take().unwrap();
+
+        for &id in self.order.iter() {
+            let gnode = self.nodes[id].as_mut().unwrap();
+            gnode.take_buffers(&mut outputs_cache, &mut feedback_cache, &mut shared_cache, &mut cursor_cache, &mut inputs, &mut outputs);
+            gnode.update(&mut inputs, &mut outputs, self.profiling);
+            gnode.restore_buffers(&mut outputs_cache, &mut feedback_cache, &mut shared_cache, &mut cursor_cache, &mut inputs, &mut outputs);
+        }
+        carry_feedback(&mut outputs_cache, &mut feedback_cache);
+
+        self.inputs = Some(inputs);
+        self.outputs = Some(outputs);
+        self.outputs_cache = Some(outputs_cache);
+        self.feedback_cache = Some(feedback_cache);
+        self.shared_cache = Some(shared_cache);
+        self.cursor_cache = Some(cursor_cache);
+    }
+
+    fn update_parallel(&mut self) {
+        let mut outputs_cache = self.outputs_cache.take().unwrap();
+        let mut feedback_cache = self.feedback_cache.take().unwrap();
+        let mut shared_cache = self.shared_cache.take().unwrap();
+        let mut cursor_cache = self.cursor_cache.take().unwrap();
+        let workers = self.workers.as_ref().unwrap();
+
+        for level in self.levels.iter() {
+            let mut sent = 0;
+            for &id in level.iter() {
+                let gnode = self.nodes[id].take().unwrap();
+                let mut job = Job {
+                    inputs: Vec::with_capacity(gnode.input_ids.len()),
+                    outputs: Vec::with_capacity(gnode.params.to.len()),
+                    gnode: gnode,
+                    profiling: self.profiling,
+                };
+                job.gnode.take_buffers(&mut outputs_cache, &mut feedback_cache, &mut shared_cache, &mut cursor_cache, &mut job.inputs, &mut job.outputs);
+                if level.len() == 1 {
+                    // Not worth a trip to another thread.
+                    job.gnode.update(&mut job.inputs, &mut job.outputs, job.profiling);
+                    job.gnode.restore_buffers(&mut outputs_cache, &mut feedback_cache, &mut shared_cache, &mut cursor_cache, &mut job.inputs, &mut job.outputs);
+                    self.nodes[id] = Some(job.gnode);
+                }
+                else {
+                    workers.jobs.as_ref().unwrap().send(job).unwrap();
+                    sent += 1;
+                }
+            }
+
+            for _ in 0..sent {
+                let mut job = workers.done.recv().expect("graph worker stopped");
+                job.gnode.restore_buffers(&mut outputs_cache, &mut feedback_cache, &mut shared_cache, &mut cursor_cache, &mut job.inputs, &mut job.outputs);
+                let id = job.gnode.id;
+                self.nodes[id] = Some(job.gnode);
+            }
+        }
+        carry_feedback(&mut outputs_cache, &mut feedback_cache);
+
+        self.outputs_cache = Some(outputs_cache);
+        self.feedback_cache = Some(feedback_cache);
+        self.shared_cache = Some(shared_cache);
+        self.cursor_cache = Some(cursor_cache);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::super::*;
+    // use super::*;
+
+    #[test]
+    fn it_connects() {
+        let mut g = Graph::new();
+        let input = Box::new(BaseMix::new());
+        let output = Box::new(BaseMix::new());
+        let output_id = g.connect(output, GraphNodeParams {
+            ..Default::default()
+        }).id();
+        g.connect(input, GraphNodeParams::default().to(output_id));
+    }
+
+    #[test]
+    fn it_updates() {
+        let mut g = Graph::new();
+        let input = {
+            Box::new(Capture::new(Box::new(|output| {
+                output.write_from(48, &mut (0i16..48i16).collect());
+            })))
+        };
+        // input.mix_inputs(&mut [RingBuffer::from((0i16..48i16).collect())]);
+        let output = Box::new(BaseMix::new());
+        let output_id = g.connect(output, GraphNodeParams {
+            ..Default::default()
+        }).id();
+        g.connect(input, GraphNodeParams::default().to(output_id));
+        g.update();
+        {
+            let output = g.borrow(output_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(output.accum.len(), 48);
+        }
+    }
+
+    #[test]
+    fn it_disconnects() {
+        let mut g = Graph::new();
+        let output_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let mix_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().to(output_id)).id();
+        let input_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), GraphNodeParams::default().to(mix_id).to(output_id)).id();
+        g.update();
+
+        let mix = g.disconnect(mix_id).unwrap();
+        assert!(mix.downcast_ref::<BaseMix>().is_some());
+        assert_eq!(g.disconnect(mix_id).err(), Some(GraphError::NoSuchNode(mix_id)));
+        assert_eq!(g.nodes[input_id].as_ref().unwrap().params.to, vec!(output_id));
+        assert_eq!(g.nodes[output_id].as_ref().unwrap().input_ids, vec!((input_id, 0)));
+
+        g.update();
+        {
+            let output = g.borrow(output_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(output.accum.len(), 48);
+        }
+
+        g.disconnect(input_id).unwrap();
+        assert_eq!(g.nodes[output_id].as_ref().unwrap().input_ids.len(), 0);
+        g.update();
+    }
+
+    #[test]
+    fn it_connects_sources_first() {
+        let mut g = Graph::new();
+        let input_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), Default::default()).id();
+        let mix_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().from(input_id)).id();
+        let output_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().from(mix_id)).id();
+        assert_eq!(g.order, vec!(input_id, mix_id, output_id));
+
+        g.update();
+        {
+            let output = g.borrow(output_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(output.accum.len(), 48);
+        }
+    }
+
+    #[test]
+    fn it_rejects_cycles() {
+        let mut g = Graph::new();
+        let a = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let b = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().from(a)).id();
+        let c = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().from(b)).id();
+        assert_eq!(g.add_edge(c, a), Err(GraphError::Cycle(c, a)));
+        assert_eq!(g.add_edge(a, a), Err(GraphError::Cycle(a, a)));
+        assert_eq!(g.try_connect(Box::new(BaseMix::new()), GraphNodeParams {
+            from: vec!(c),
+            to: vec!(a),
+            ..Default::default()
+        }).err(), Some(GraphError::Cycle(c, a)));
+        assert_eq!(g.order, vec!(a, b, c));
+        assert_eq!(g.add_edge(a, c), Ok(()));
+        g.update();
+    }
+
+    #[test]
+    fn it_exports_edge_states() {
+        let mut g = Graph::new();
+        // Capture ignores its inputs so samples build up on its edge.
+        let output_id = g.connect(Box::new(Capture::new(Box::new(|_| {}))), Default::default()).id();
+        let mix_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), GraphNodeParams::default().to(output_id).to(mix_id));
+        g.update();
+
+        assert_eq!(g.edge_states(), vec!(
+            EdgeState {from: 2, from_port: "main", to: 0, to_port: "main", len: 48, active: true, feedback: false,
+                overflow: OverflowPolicy::DropOldest, overruns: 0, underruns: 0},
+            EdgeState {from: 2, from_port: "main", to: 1, to_port: "main", len: 0, active: true, feedback: false,
+                overflow: OverflowPolicy::DropOldest, overruns: 0, underruns: 0},
+        ));
+        assert_eq!(g.to_dot(), "digraph audio {\n    0 [label=\"capture\"];\n    1 [label=\"base_mix\"];\n    2 [label=\"capture\"];\n    2 -> 0 [label=\"48\"];\n    2 -> 1 [label=\"0\"];\n}\n");
+        assert_eq!(g.to_json(), concat!(
+            r#"{"nodes":[{"id":0,"kind":"capture","name":null,"channels":null,"rate":null,"latency_ms":null,"status":"running"},"#,
+            r#"{"id":1,"kind":"base_mix","name":null,"channels":null,"rate":null,"latency_ms":null,"status":"running"},"#,
+            r#"{"id":2,"kind":"capture","name":null,"channels":null,"rate":null,"latency_ms":null,"status":"running"}],"#,
+            r#""edges":[{"from":2,"from_port":"main","to":0,"to_port":"main","len":48,"active":true,"feedback":false,"overruns":0,"underruns":0},"#,
+            r#"{"from":2,"from_port":"main","to":1,"to_port":"main","len":0,"active":true,"feedback":false,"overruns":0,"underruns":0}]}"#
+        ));
+    }
+
+    #[test]
+    fn it_escapes_names() {
+        let mut g = Graph::new();
+        g.connect(Box::new(Capture::new(Box::new(|_| {})).with_name("say \"hi\"\\\u{1}é")), Default::default());
+
+        assert_eq!(g.to_dot(), "digraph audio {\n    0 [label=\"say \\\"hi\\\"\\\\\u{1}é\"];\n}\n");
+        assert!(g.to_json().contains(r#""name":"say \"hi\"\\\u0001é","#));
+    }
+
+    #[test]
+    fn it_describes_nodes() {
+        let mut g = Graph::new();
+        let capture_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.active = false;
+        })).with_name("line in").with_format(StreamFormat::new(44100, 2))), Default::default()).id();
+        let mix_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().from(capture_id)).id();
+        assert_eq!(g.info(capture_id).unwrap().status, NodeStatus::Idle);
+        g.update();
+
+        assert_eq!(g.info(capture_id), Some(NodeInfo {
+            kind: "capture",
+            name: Some("line in".to_string()),
+            channels: Some(2),
+            rate: Some(44100),
+            latency: None,
+            status: NodeStatus::Inactive,
+        }));
+        // The mix doesn't know its format on its own.
+        let mix_info = g.info(mix_id).unwrap();
+        assert_eq!((mix_info.label(), mix_info.rate, mix_info.channels), ("base_mix", Some(44100), Some(2)));
+        assert_eq!(g.info(mix_id + 1), None);
+    }
+
+    #[test]
+    fn it_profiles_nodes() {
+        let mut g = Graph::new();
+        let output_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let input_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), GraphNodeParams::default().to(output_id)).id();
+        g.update();
+        assert_eq!(g.profile(input_id).unwrap().updates, 0);
+
+        g.set_profiling(true);
+        g.update();
+        g.update();
+        {
+            let profile = g.profile(input_id).unwrap();
+            assert_eq!(profile.updates, 2);
+            assert!(profile.max_ns >= profile.last_ns);
+            assert!(profile.max_ns >= profile.average_ns());
+            assert_eq!(profile.histogram.iter().sum::<u64>(), 2);
+        }
+        assert_eq!(g.profile(output_id).unwrap().updates, 2);
+        assert!(g.profile(10usize).is_none());
+
+        g.set_profiling(false);
+        g.update();
+        assert_eq!(g.profile(input_id).unwrap().updates, 2);
+    }
+
+    #[test]
+    fn it_buckets_update_times() {
+        let mut profile = NodeProfile::default();
+        profile.record(500);
+        profile.record(1500);
+        profile.record(3000);
+        profile.record(1000000000);
+        assert_eq!(&profile.histogram[0..3], &[1, 1, 1]);
+        assert_eq!(profile.histogram[PROFILE_BUCKETS - 1], 1);
+        assert_eq!(profile.max_ns, 1000000000);
+        assert_eq!(profile.last_ns, 1000000000);
+    }
+
+    #[test]
+    fn it_rewires_edges() {
+        let mut g = Graph::new();
+        let chat_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let transmitter_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let input_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), GraphNodeParams::default().to(chat_id)).id();
+        g.update();
+
+        assert_eq!(g.add_edge(input_id, chat_id), Err(GraphError::EdgeExists(input_id, chat_id)));
+        assert_eq!(g.remove_edge(input_id, transmitter_id), Err(GraphError::NoSuchEdge(input_id, transmitter_id)));
+        assert_eq!(g.add_edge(input_id, 10usize), Err(GraphError::NoSuchNode(10)));
+
+        assert_eq!(g.add_edge(input_id, transmitter_id), Ok(()));
+        assert_eq!(g.remove_edge(input_id, chat_id), Ok(()));
+        assert_eq!(g.nodes[transmitter_id].as_ref().unwrap().input_ids, vec!((input_id, 0)));
+        assert_eq!(g.nodes[chat_id].as_ref().unwrap().input_ids.len(), 0);
+
+        g.update();
+        {
+            let transmitter = g.borrow(transmitter_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(transmitter.accum.len(), 48);
+        }
+    }
+
+    #[test]
+    fn it_updates_levels_on_threads() {
+        let mut g = Graph::new();
+        let mix_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let mut branch_ids = Vec::new();
+        for i in 1..4 {
+            let branch_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().to(mix_id)).id();
+            g.connect(Box::new(Capture::new(Box::new(move |output| {
+                output.write_from(48, &mut (0..48).map(|_| i).collect());
+            }))), GraphNodeParams::default().to(branch_id));
+            branch_ids.push(branch_id);
+        }
+        assert_eq!(g.levels.len(), 3);
+        assert_eq!(g.levels[1].len(), 3);
+        assert_eq!(g.levels[2], vec!(mix_id));
+
+        g.set_threads(2);
+        g.set_profiling(true);
+        for _ in 0..4 {
+            g.update();
+        }
+        {
+            let mix = g.borrow(mix_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(mix.accum.len(), 48);
+            assert_eq!(mix.accum[0], 1 + 2 + 3);
+        }
+        for &branch_id in branch_ids.iter() {
+            assert_eq!(g.profile(branch_id).unwrap().updates, 4);
+        }
+
+        g.set_threads(0);
+        g.update();
+        assert_eq!(g.profile(mix_id).unwrap().updates, 5);
+    }
+
+    #[test]
+    fn it_gets_typed_nodes() {
+        let mut g = Graph::new();
+        let mix_id = g.connect(Box::new(BaseMix::new()), Default::default());
+        let boxed: Box<Node> = Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        })));
+        let capture_id = g.connect(boxed, GraphNodeParams::default().to(mix_id));
+        g.update();
+
+        assert_eq!(g.get(mix_id).unwrap().accum.len(), 48);
+        g.get_mut(mix_id).unwrap().accum.clear();
+        assert_eq!(g.get(mix_id).unwrap().accum.len(), 0);
+
+        g.disconnect(capture_id).unwrap();
+        g.disconnect(mix_id).unwrap();
+        assert!(g.get(mix_id).is_none());
+    }
+
+    fn feedback_graph() -> (Graph, usize) {
+        let mut g = Graph::new();
+        let bus_id = g.connect(Box::new(BaseMix::new()), Default::default()).id();
+        let monitor_id = g.connect(Box::new(Callback::new(Box::new(|input, output| {
+            let avail = input.len();
+            output.write_from_ring(avail, input);
+        }))), GraphNodeParams {
+            from: vec!(bus_id),
+            feedback_to: vec!(bus_id),
+            ..Default::default()
+        }).id();
+        g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0..48).map(|_| 1).collect());
+        }))), GraphNodeParams::default().to(bus_id));
+        assert_eq!(g.add_edge(monitor_id, bus_id), Err(GraphError::Cycle(monitor_id, bus_id)));
+        assert_eq!(g.add_feedback_edge(monitor_id, bus_id), Err(GraphError::EdgeExists(monitor_id, bus_id)));
+        (g, bus_id)
+    }
+
+    #[test]
+    fn it_delays_feedback_edges() {
+        let (mut g, bus_id) = feedback_graph();
+        for &expected in [1, 2, 3, 4].iter() {
+            g.update();
+            let bus = g.borrow(bus_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(bus.accum.len(), 48);
+            assert_eq!(bus.accum[0], expected);
+        }
+        assert!(g.edge_states().iter().any(|edge| edge.feedback && edge.len == 48));
+
+        let (mut g, bus_id) = feedback_graph();
+        g.set_threads(2);
+        for &expected in [1, 2, 3, 4].iter() {
+            g.update();
+            let bus = g.borrow(bus_id).downcast_ref::<BaseMix>().unwrap();
+            assert_eq!(bus.accum[0], expected);
+        }
+    }
+
+    // Doubles what it reads and notes the hooks it's called with.
+    struct Lifecycle {
+        events: Vec<&'static str>,
+    }
+
+    impl Node for Lifecycle {
+        fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+            for input in inputs.iter_mut() {
+                let avail = input.len();
+                let slice = input.read_slice(avail);
+                for output in outputs.iter_mut() {
+                    for (i, o) in slice.iter().zip(output.write_slice(avail).iter_mut()) {
+                        *o = *i * 2;
+                    }
+                }
+            }
+        }
+
+        fn start(&mut self) {
+            self.events.push("start");
+        }
+
+        fn stop(&mut self) {
+            self.events.push("stop");
+        }
+
+        fn reset(&mut self) {
+            self.events.push("reset");
+        }
+
+        fn flush(&mut self) {
+            self.events.push("flush");
+        }
+    }
+
+    #[test]
+    fn it_runs_node_lifecycles() {
+        let mut g = Graph::new();
+        let sink_id = g.connect(RenderSink::new(StreamFormat::any()), Default::default());
+        let node_id = g.connect(Box::new(Lifecycle {events: Vec::new()}), GraphNodeParams::default().to(sink_id));
+        g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(4, &vec!(1; 4));
+        }))), GraphNodeParams::default().to(node_id));
+        assert_eq!(g.get(node_id).unwrap().events, vec!("start"));
+        g.update();
+
+        assert_eq!(g.set_bypass(node_id, true), Ok(()));
+        assert_eq!(g.info(node_id).unwrap().status, NodeStatus::Bypassed);
+        g.update();
+        assert_eq!(g.get(sink_id).unwrap().samples(), &[2, 2, 2, 2, 1, 1, 1, 1]);
+
+        g.restart();
+        assert_eq!(g.edge_states().iter().map(|edge| edge.len).sum::<usize>(), 0);
+        assert_eq!(g.set_bypass(node_id, false), Ok(()));
+        assert_eq!(g.get(node_id).unwrap().events, vec!("start", "stop", "flush", "flush", "reset", "reset", "start"));
+
+        let node = g.disconnect(node_id).unwrap();
+        assert_eq!(node.downcast_ref::<Lifecycle>().unwrap().events.last(), Some(&"stop"));
+    }
+
+    // Claims to write 44100 Hz from 48000 Hz.
+    struct Resample;
+
+    impl Node for Resample {
+        fn update(&mut self, _: &mut [RingBuffer], _: &mut [RingBuffer]) {}
+
+        fn input_format(&self) -> StreamFormat {
+            StreamFormat::any().with_rate(48000)
+        }
+
+        fn output_format(&self, input: StreamFormat) -> StreamFormat {
+            input.with_rate(44100)
+        }
+    }
+
+    #[test]
+    fn it_rejects_bypasses_changing_formats() {
+        let mut g = Graph::new();
+        let sink_id = g.connect(RenderSink::new(StreamFormat::new(44100, 2)), Default::default()).id();
+        let rate_id = g.connect(Box::new(Resample), GraphNodeParams::default().to(sink_id)).id();
+        g.connect(RenderSource::new(Vec::new(), 4, StreamFormat::new(48000, 2)), GraphNodeParams::default().to(rate_id));
+        assert_eq!(g.set_bypass(rate_id, true), Err(GraphError::FormatMismatch(rate_id, StreamFormat::new(48000, 2), sink_id, StreamFormat::new(44100, 2))));
+        assert!(!g.is_bypassed(rate_id));
+    }
+
+    struct PortSink {
+        main: usize,
+        sidechain: usize,
+    }
+
+    const PORT_SINK_INPUTS: &'static [&'static str] = &["main", "sidechain"];
+
+    impl Node for PortSink {
+        fn update(&mut self, inputs: &mut [RingBuffer], _: &mut [RingBuffer]) {
+            self.main = port_inputs(inputs, 0).iter().map(|input| input.len()).sum();
+            self.sidechain = port_inputs(inputs, 1).iter().map(|input| input.len()).sum();
+        }
+
+        fn input_ports(&self) -> &[&'static str] {
+            PORT_SINK_INPUTS
+        }
+    }
+
+    #[test]
+    fn it_connects_ports() {
+        let mut g = Graph::new();
+        let sink_id = g.connect(Box::new(PortSink {main: 0, sidechain: 0}), Default::default()).id();
+        let sidechain_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), GraphNodeParams {
+            to_ports: vec!(("main", sink_id, "sidechain")),
+            ..Default::default()
+        }).id();
+        let main_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(16, &mut (0i16..16i16).collect());
+        }))), Default::default()).id();
+
+        assert_eq!(g.add_port_edge(main_id, "main", sink_id, "aux"), Err(GraphError::NoSuchPort(sink_id, "aux")));
+        assert_eq!(g.add_port_edge(main_id, "main", sink_id, "main"), Ok(()));
+        assert_eq!(g.add_port_edge(sidechain_id, "main", sink_id, "main"), Ok(()));
+        assert_eq!(g.add_port_edge(sidechain_id, "main", sink_id, "main"), Err(GraphError::EdgeExists(sidechain_id, sink_id)));
+        assert_eq!(g.nodes[sink_id].as_ref().unwrap().input_ids, vec!((main_id, 0), (sidechain_id, 1), (sidechain_id, 0)));
+
+        g.update();
+        {
+            let sink = g.borrow(sink_id).downcast_ref::<PortSink>().unwrap();
+            assert_eq!(sink.main, 16 + 48);
+            assert_eq!(sink.sidechain, 48);
+        }
+
+        assert_eq!(g.remove_port_edge(sidechain_id, "main", sink_id, "main"), Ok(()));
+        assert_eq!(g.remove_port_edge(sidechain_id, "main", sink_id, "main"), Err(GraphError::NoSuchEdge(sidechain_id, sink_id)));
+        assert_eq!(g.nodes[sink_id].as_ref().unwrap().input_ids, vec!((main_id, 0), (sidechain_id, 0)));
+
+        assert_eq!(g.remove_edge(sidechain_id, sink_id), Ok(()));
+        assert_eq!(g.nodes[sink_id].as_ref().unwrap().input_ids, vec!((main_id, 0)));
+    }
+
+    #[test]
+    fn it_checks_formats() {
+        let mut g = Graph::new();
+        let out_id = g.connect(Box::new(Playback::new(Box::new(|_| {})).with_format(StreamFormat::new(48000, 2))), Default::default()).id();
+        let mix_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().to(out_id)).id();
+        let mono = StreamFormat::new(48000, 1);
+        let mono_capture = || Box::new(Capture::new(Box::new(|_| {})).with_format(mono));
+        assert_eq!(g.try_connect(mono_capture(), GraphNodeParams::default().to(mix_id)).err(), Some(GraphError::FormatMismatch(mix_id, mono, out_id, StreamFormat::new(48000, 2))));
+        assert_eq!(g.node_ids(), vec!(out_id, mix_id));
+        assert_eq!(g.format(mix_id), Some(StreamFormat::any()));
+
+        let stereo_id = g.connect(Box::new(Capture::new(Box::new(|_| {})).with_format(StreamFormat::new(48000, 2))), GraphNodeParams::default().to(mix_id)).id();
+        assert_eq!(stereo_id, 2);
+        assert_eq!(g.format(mix_id), Some(StreamFormat::new(48000, 2)));
+
+        let mono_id = g.connect(mono_capture(), Default::default()).id();
+        let err = g.add_edge(mono_id, mix_id).unwrap_err();
+        assert_eq!(format!("{}", err), "node 3 writes 48000 Hz, 1 channel but node 1 reads 48000 Hz, 2 channels");
+        assert_eq!(g.edge_states().len(), 2);
+        assert_eq!(g.add_feedback_edge(mono_id, out_id), Err(GraphError::FormatMismatch(mono_id, mono, out_id, StreamFormat::new(48000, 2))));
+        g.update();
+    }
+
+    #[test]
+    fn it_counts_edge_overruns() {
+        let mut g = Graph::new();
+        // Capture ignores its inputs so samples build up on its edges.
+        let stalled_id = g.connect(Box::new(Capture::new(Box::new(|_| {}))), Default::default()).id();
+        let resync_id = g.connect(Box::new(Capture::new(Box::new(|_| {}))), Default::default()).id();
+        let input_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(20000, &mut (0..20000).map(|_| 1).collect());
+        }))), GraphNodeParams {
+            to: vec!(stalled_id, resync_id),
+            overflow: vec!((resync_id, OverflowPolicy::Resync)),
+            ..Default::default()
+        }).id();
+        assert_eq!(g.set_overflow(input_id, input_id, OverflowPolicy::DropNewest), Err(GraphError::NoSuchEdge(input_id, input_id)));
+        g.update();
+        g.update();
+
+        let edges = g.edge_states();
+        assert_eq!((edges[0].to, edges[0].len, edges[0].overflow, edges[0].overruns), (stalled_id, 32768, OverflowPolicy::DropOldest, 1));
+        assert_eq!((edges[1].to, edges[1].len, edges[1].overflow, edges[1].overruns), (resync_id, 20000, OverflowPolicy::Resync, 1));
+
+        assert_eq!(g.set_overflow(input_id, stalled_id, OverflowPolicy::DropNewest), Ok(()));
+        g.update();
+        assert_eq!(g.edge_states()[0].overruns, 2);
+        assert_eq!(g.edge_states()[0].len, 32768);
+    }
+
+    #[test]
+    fn it_shares_fan_out_rings() {
+        let mut g = Graph::new();
+        let input_id = g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), Default::default()).id();
+        let fast_id = g.connect(Box::new(BaseMix::new()), GraphNodeParams::default().from(input_id));
+        let slow_id = g.connect(Box::new(Callback::new(Box::new(|input, _| {
+            input.read_slice(16);
+        }))), GraphNodeParams::default().from(input_id)).id();
+        // Readers of one ring update on the same level.
+        assert_eq!(g.levels, vec!(vec!(input_id), vec!(slow_id, fast_id.id())));
+        g.set_threads(2);
+        g.update();
+        g.update();
+
+        let mix = g.get(fast_id).unwrap();
+        assert_eq!(mix.accum.len(), 48);
+        assert_eq!(mix.accum[47], 47);
+        let edges = g.edge_states();
+        assert_eq!((edges[0].to, edges[0].len), (fast_id.id(), 0));
+        assert_eq!((edges[1].to, edges[1].len), (slow_id, 64));
     }
 }
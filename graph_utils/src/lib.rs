@@ -1,22 +1,38 @@
+mod sample;
 mod ring_buffer;
+mod spsc;
+mod frame;
+mod format;
 mod node;
 mod copy_out;
 mod base_mix;
 mod callback;
 mod capture;
 mod playback;
+mod clock;
 mod graph;
-// mod param;
+mod sub_graph;
+mod offline;
+mod wav;
+mod param;
 
+pub use self::sample::*;
 pub use self::ring_buffer::*;
+pub use self::spsc::*;
+pub use self::frame::*;
+pub use self::format::*;
 pub use self::node::*;
 pub use self::copy_out::*;
 pub use self::base_mix::*;
 pub use self::callback::*;
 pub use self::capture::*;
 pub use self::playback::*;
+pub use self::clock::*;
 pub use self::graph::*;
-// pub use self::param::*;
+pub use self::sub_graph::*;
+pub use self::offline::*;
+pub use self::wav::*;
+pub use self::param::*;
 // pub mod capture;
 // pub mod playback;
 // pub mod graph;
@@ -1,21 +1,168 @@
 use std::any::Any;
+use std::fmt;
+use std::time::Duration;
 
-use super::{RingBuffer};
+use super::{RingBuffer, StreamFormat};
 
 pub trait NodeAsAny : Any {
     fn as_any(&self) -> &Any;
     fn as_mut_any(&mut self) -> &mut Any;
 }
 
-pub trait Node : NodeAsAny {
+// How a node is doing as of its last update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeStatus {
+    // Not updated yet.
+    Idle,
+    Running,
+    // Updated but reading or writing nothing active, like a capture whose
+    // device isn't connected.
+    Inactive,
+    // Skipped by its graph, which passes its inputs straight through.
+    Bypassed,
+}
+
+impl Default for NodeStatus {
+    fn default() -> NodeStatus {
+        NodeStatus::Running
+    }
+}
+
+impl fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match *self {
+            NodeStatus::Idle => "idle",
+            NodeStatus::Running => "running",
+            NodeStatus::Inactive => "inactive",
+            NodeStatus::Bypassed => "bypassed",
+        })
+    }
+}
+
+// What a node is, for tools and logs to describe a graph with instead of
+// node ids.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NodeInfo {
+    // The type of node, like "volume" or "capture".
+    pub kind: &'static str,
+    // What this node is among others of its kind, like the card a capture
+    // reads.
+    pub name: Option<String>,
+    pub channels: Option<usize>,
+    pub rate: Option<usize>,
+    // How long samples take to get through the node, if it knows.
+    pub latency: Option<Duration>,
+    pub status: NodeStatus,
+}
+
+impl NodeInfo {
+    pub fn new(kind: &'static str) -> NodeInfo {
+        NodeInfo {
+            kind: kind,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_name(self, name: &str) -> NodeInfo {
+        NodeInfo {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    pub fn with_format(self, format: StreamFormat) -> NodeInfo {
+        NodeInfo {
+            rate: format.rate,
+            channels: format.channels,
+            ..self
+        }
+    }
+
+    pub fn with_latency(self, latency: Duration) -> NodeInfo {
+        NodeInfo {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    pub fn with_status(self, status: NodeStatus) -> NodeInfo {
+        NodeInfo {
+            status: status,
+            ..self
+        }
+    }
+
+    // The name if there is one, otherwise the kind.
+    pub fn label(&self) -> &str {
+        self.name.as_ref().map_or(self.kind, |name| &name[..])
+    }
+}
+
+pub const MAIN_PORTS: &'static [&'static str] = &["main"];
+
+pub trait Node : NodeAsAny + Send {
+    // inputs holds a ring for each edge into the node. outputs holds a ring
+    // for each edge out of it, except that edges fanning out of one port to
+    // different nodes share a single ring, so a node can't count its edges by
+    // its outputs and writes what it means for a port to each of the port's
+    // outputs, like copy_out does.
     fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]);
-    // fn is_input(&self) -> bool;
-    // fn is_output(&self) -> bool;
-    // fn channels(&self) -> usize;
-    // fn available(&self) -> usize;
+    // Port names edges can connect to. inputs are sorted by to_port and
+    // outputs by from_port, indexing into these.
+    fn input_ports(&self) -> &[&'static str] {
+        MAIN_PORTS
+    }
+    fn output_ports(&self) -> &[&'static str] {
+        MAIN_PORTS
+    }
+    // The format this node reads. Graph checks every edge into the node
+    // against it.
+    fn input_format(&self) -> StreamFormat {
+        StreamFormat::any()
+    }
+    // The format this node writes given the format it reads.
+    fn output_format(&self, input: StreamFormat) -> StreamFormat {
+        input
+    }
+    // Graph calls this with the format reaching the node's inputs each time
+    // the edges around it change, so a node can follow what it's given, like
+    // how many channels a frame has.
+    fn set_input_format(&mut self, _: StreamFormat) {}
+    // What this node is. The format here is what the node writes as far as
+    // it knows on its own. Graph::info fills in the rest.
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("node").with_format(self.output_format(self.input_format()))
+    }
+    // Graph calls start when the node is connected, stop when it is
+    // disconnected and both around bypassing it or restarting the graph.
+    fn start(&mut self) {}
+    fn stop(&mut self) {}
+    // Forget what earlier updates built up, like a resampler's carry, as if
+    // the node were new.
+    fn reset(&mut self) {}
+    // Drop samples held between updates.
+    fn flush(&mut self) {}
     // fn read_into(&mut self, samples: usize, buffer: &mut Vec<i16>);
 }
 
+// Lets Graph::connect take a box of a node type, keeping the type for its
+// NodeId, or a node already boxed as a Box<Node>.
+pub trait IntoBoxNode {
+    fn into_box_node(self: Box<Self>) -> Box<Node>;
+}
+
+impl<T: Node> IntoBoxNode for T {
+    fn into_box_node(self: Box<Self>) -> Box<Node> {
+        self
+    }
+}
+
+impl IntoBoxNode for Node {
+    fn into_box_node(self: Box<Self>) -> Box<Node> {
+        self
+    }
+}
+
 impl<T> NodeAsAny for T where T : Any {
   fn as_any(&self) -> &Any {
     self as &Any
@@ -35,6 +182,20 @@ impl Node {
     }
 }
 
+// The inputs connected to one input port.
+pub fn port_inputs(inputs: &mut [RingBuffer], port: usize) -> &mut [RingBuffer] {
+    let start = inputs.iter().take_while(|input| input.to_port < port).count();
+    let end = inputs.iter().take_while(|input| input.to_port <= port).count();
+    &mut inputs[start..end]
+}
+
+// The outputs connected to one output port.
+pub fn port_outputs(outputs: &mut [RingBuffer], port: usize) -> &mut [RingBuffer] {
+    let start = outputs.iter().take_while(|output| output.from_port < port).count();
+    let end = outputs.iter().take_while(|output| output.from_port <= port).count();
+    &mut outputs[start..end]
+}
+
 // #[cfg(test)]
 // mod test {
 // }
@@ -0,0 +1,183 @@
+use std::cmp::min;
+use std::io::{self, Write};
+use std::time::Duration;
+
+use super::{Node, NodeInfo, RingBuffer, StreamFormat, BaseMix, Clock, Graph, write_wav};
+
+// Drives a graph in fixed blocks of simulated time instead of as fast as
+// devices produce and consume samples.
+pub struct OfflineRender {
+    clock: Clock,
+    rate: usize,
+    channels: usize,
+    block_frames: usize,
+}
+
+// Plays samples into a rendered graph one block per update.
+pub struct RenderSource {
+    samples: Vec<i16>,
+    position: usize,
+    block_samples: usize,
+    format: StreamFormat,
+}
+
+// Keeps everything a rendered graph plays into it.
+pub struct RenderSink {
+    base_mix: BaseMix,
+    ring: RingBuffer,
+    samples: Vec<i16>,
+    format: StreamFormat,
+}
+
+impl OfflineRender {
+    pub fn new(clock: Clock, rate: usize, channels: usize, block_frames: usize) -> OfflineRender {
+        assert!(clock.is_simulated(), "offline renders need a simulated clock");
+        OfflineRender {
+            clock: clock,
+            rate: rate,
+            channels: channels,
+            block_frames: block_frames,
+        }
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &self.clock
+    }
+
+    pub fn block_samples(&self) -> usize {
+        self.block_frames * self.channels
+    }
+
+    pub fn format(&self) -> StreamFormat {
+        StreamFormat::new(self.rate, self.channels)
+    }
+
+    pub fn block_duration(&self) -> Duration {
+        let nanos = self.block_frames as u64 * 1000000000 / self.rate as u64;
+        Duration::new(nanos / 1000000000, (nanos % 1000000000) as u32)
+    }
+
+    pub fn source(&self, samples: Vec<i16>) -> Box<RenderSource> {
+        RenderSource::new(samples, self.block_samples(), self.format())
+    }
+
+    pub fn sink(&self) -> Box<RenderSink> {
+        RenderSink::new(self.format())
+    }
+
+    // Update the graph once per block, advancing the clock a block each time.
+    pub fn render(&self, graph: &mut Graph, blocks: usize) {
+        let block_duration = self.block_duration();
+        for _ in 0..blocks {
+            graph.update();
+            self.clock.advance(block_duration);
+        }
+    }
+
+    pub fn write_wav<W: Write>(&self, writer: &mut W, sink: &RenderSink) -> io::Result<()> {
+        write_wav(writer, self.rate as u32, self.channels as u16, sink.samples())
+    }
+}
+
+impl RenderSource {
+    pub fn new(samples: Vec<i16>, block_samples: usize, format: StreamFormat) -> Box<RenderSource> {
+        Box::new(RenderSource {
+            samples: samples,
+            position: 0,
+            block_samples: block_samples,
+            format: format,
+        })
+    }
+}
+
+impl Node for RenderSource {
+    fn update(&mut self, _: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+        let end = min(self.position + self.block_samples, self.samples.len());
+        let block = &self.samples[self.position..end];
+        for output in outputs.iter_mut() {
+            output.active = block.len() > 0;
+            for (i, o) in block.iter().zip(output.write_slice(block.len()).iter_mut()) {
+                *o = *i;
+            }
+        }
+        self.position = end;
+    }
+
+    fn output_format(&self, _: StreamFormat) -> StreamFormat {
+        self.format
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("render_source").with_format(self.format)
+    }
+}
+
+impl RenderSink {
+    pub fn new(format: StreamFormat) -> Box<RenderSink> {
+        Box::new(RenderSink {
+            base_mix: BaseMix::new(),
+            ring: RingBuffer::new(),
+            samples: Vec::new(),
+            format: format,
+        })
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+}
+
+impl Node for RenderSink {
+    fn update(&mut self, inputs: &mut [RingBuffer], _: &mut [RingBuffer]) {
+        self.base_mix.mix_inputs_ring(inputs, &mut self.ring);
+        let avail = self.ring.len();
+        self.samples.extend(self.ring.read_slice(avail).iter().cloned());
+    }
+
+    fn input_format(&self) -> StreamFormat {
+        self.format
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("render_sink").with_format(self.format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{OfflineRender, RenderSink};
+    use super::super::{Clock, Callback, Graph, GraphNodeParams};
+
+    #[test]
+    fn it_renders_blocks() {
+        let render = OfflineRender::new(Clock::simulated(), 48000, 2, 480);
+        assert_eq!(render.block_duration(), Duration::from_millis(10));
+
+        let mut g = Graph::new();
+        let sink_id = g.connect(render.sink(), Default::default());
+        let start = render.clock().now();
+        let clock = render.clock().clone();
+        // Mutes everything after the first 20ms.
+        let gate_id = g.connect(Box::new(Callback::new(Box::new(move |input, output| {
+            let avail = input.len();
+            let slice = input.read_slice(avail);
+            let open = clock.now().duration_since(start) < Duration::from_millis(20);
+            for (i, o) in slice.iter().zip(output.write_slice(avail).iter_mut()) {
+                *o = if open {*i} else {0};
+            }
+        }))), GraphNodeParams::default().to(sink_id));
+        g.connect(render.source((0..960 * 4).map(|_| 100).collect()), GraphNodeParams::default().to(gate_id));
+
+        render.render(&mut g, 5);
+
+        let sink = g.get(sink_id).unwrap();
+        assert_eq!(sink.samples().len(), 960 * 4);
+        assert_eq!(sink.samples().iter().filter(|&&s| s == 100).count(), 960 * 2);
+
+        let mut bytes = Vec::new();
+        render.write_wav(&mut bytes, sink).unwrap();
+        assert_eq!(bytes.len(), 44 + 960 * 4 * 2);
+    }
+}
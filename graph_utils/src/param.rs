@@ -1,77 +1,408 @@
-// use std::rc::*;
-//
-// pub struct ParamRW<T> {
-//     value: Rc<T>,
-// }
-//
-// impl<T> ParamRW<T> where T : Copy {
-//     pub fn new(t: T) -> ParamRW<T> {
-//         ParamRW {
-//             value: Rc::new(t),
-//         }
-//     }
-//
-//     pub fn get(&self) -> T {
-//         *self.value
-//     }
-//
-//     pub fn set(&mut self, t: T) {
-//         if let Some(value) = Rc::get_mut(&mut self.value) {
-//             *value = t;
-//         }
-//         else {
-//             panic!("Cannot get mutable reference to ParamRW");
-//         }
-//     }
-//
-//     pub fn clone_read(&self) -> ParamRead<T> {
-//         ParamRead {
-//             value: Rc::downgrade(&self.value),
-//         }
-//     }
-// }
-//
-// pub struct ParamRead<T> {
-//     value: Weak<T>,
-// }
-//
-// impl<T> ParamRead<T> where T : Copy {
-//     pub fn get(&self) -> Option<T> {
-//         if let Some(value) = self.value.upgrade() {
-//             Some(*value)
-//         }
-//         else {
-//             None
-//         }
-//     }
-// }
-//
-// #[cfg(test)]
-// mod test {
-//     use super::*;
-//
-//     #[test]
-//     fn it_reads() {
-//         let p = ParamRW::new(1 as i32);
-//         assert_eq!(p.get(), 1);
-//     }
-//
-//     #[test]
-//     fn it_writes() {
-//         let mut p = ParamRW::new(1 as i32);
-//         assert_eq!(p.get(), 1);
-//         p.set(2);
-//         assert_eq!(p.get(), 2);
-//     }
-//
-//     #[test]
-//     fn it_reads_clones() {
-//         let mut p = ParamRW::new(1 as i32);
-//         assert_eq!(p.get(), 1);
-//         let q = p.clone_read();
-//         assert_eq!(q.get().unwrap(), 1);
-//         p.set(2);
-//         assert_eq!(p.get(), 2);
-//         assert_eq!(q.get().unwrap(), 2);
-//     }
-// }
+use std::cmp::min;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use super::{Sample, StreamFormat};
+
+// Samples a gain change is spread over by default, 5ms of 48000 Hz stereo.
+pub const SMOOTHING: usize = 480;
+
+// How a scheduled change moves from the value before it to its target.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ramp {
+    Step,
+    Linear,
+    // Moves by the same ratio each sample, so a gain ramp sounds even. Ramps
+    // from or to 0 start or end at MIN_EXPONENTIAL and jump the rest.
+    Exponential,
+}
+
+// The smallest value an exponential ramp moves through, -80db as a gain.
+pub const MIN_EXPONENTIAL: f32 = 0.0001;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamEvent {
+    // The sample position, as counted by ParamRW::position, the change
+    // starts at. None starts it with the next update.
+    pub at: Option<usize>,
+    pub ramp: Ramp,
+    pub target: f32,
+    pub duration: Duration,
+}
+
+impl ParamEvent {
+    pub fn new(ramp: Ramp, target: f32, duration: Duration) -> ParamEvent {
+        ParamEvent {
+            at: None,
+            ramp: ramp,
+            target: target,
+            duration: duration,
+        }
+    }
+
+    pub fn at(self, position: usize) -> ParamEvent {
+        ParamEvent {
+            at: Some(position),
+            ..self
+        }
+    }
+}
+
+// A value control threads, like http handlers, set and nodes read without
+// locking. Clones set and read the same value.
+#[derive(Clone, Debug)]
+pub struct ParamRW {
+    // the f32's bits, written only by set
+    value: Arc<AtomicUsize>,
+    // how many times set was called, so setting the same value again moves
+    // the reader back to it
+    sets: Arc<AtomicUsize>,
+    // the bits of the value the reader is moving to, written only by the
+    // reader
+    target: Arc<AtomicUsize>,
+    // samples the reader has moved through
+    position: Arc<AtomicUsize>,
+    events: Arc<Mutex<Vec<ParamEvent>>>,
+}
+
+impl ParamRW {
+    pub fn new(value: f32) -> ParamRW {
+        ParamRW {
+            value: Arc::new(AtomicUsize::new(value.to_bits() as usize)),
+            sets: Arc::new(AtomicUsize::new(0)),
+            target: Arc::new(AtomicUsize::new(value.to_bits() as usize)),
+            position: Arc::new(AtomicUsize::new(0)),
+            events: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    // The value last set.
+    pub fn get(&self) -> f32 {
+        f32::from_bits(self.value.load(Ordering::Relaxed) as u32)
+    }
+
+    // The value the reader is moving to, the value last set or the target of
+    // the last scheduled change to start.
+    pub fn target(&self) -> f32 {
+        f32::from_bits(self.target.load(Ordering::Relaxed) as u32)
+    }
+
+    // Move to value over the reader's smoothing, dropping any ramp in
+    // progress. Scheduled changes still start when they are due.
+    pub fn set(&self, value: f32) {
+        self.value.store(value.to_bits() as usize, Ordering::Relaxed);
+        self.sets.fetch_add(1, Ordering::Release);
+    }
+
+    // The sample position the reader got to in its last update. It wraps,
+    // like after 12 hours of 48000 Hz stereo with a 32 bit usize.
+    pub fn position(&self) -> usize {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    // Queue a change for the reader to pick up in its next update.
+    pub fn schedule(&self, event: ParamEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    // Jump to value at a sample position.
+    pub fn set_at(&self, value: f32, position: usize) {
+        self.schedule(ParamEvent::new(Ramp::Step, value, Duration::new(0, 0)).at(position));
+    }
+
+    pub fn linear_ramp(&self, target: f32, duration: Duration) {
+        self.schedule(ParamEvent::new(Ramp::Linear, target, duration));
+    }
+
+    pub fn exponential_ramp(&self, target: f32, duration: Duration) {
+        self.schedule(ParamEvent::new(Ramp::Exponential, target, duration));
+    }
+
+    // A reader for a node that moves to each new value over smoothing
+    // samples instead of jumping to it.
+    pub fn clone_read(&self, smoothing: usize) -> ParamRead {
+        let value = self.get();
+        ParamRead {
+            param: self.clone(),
+            current: value,
+            target: value,
+            sets: self.sets.load(Ordering::Acquire),
+            ramp: Ramp::Linear,
+            step: 0.0,
+            remaining: 0,
+            smoothing: smoothing,
+            position: self.position(),
+            samples_per_second: 48000 * 2,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+// Whether a change at position at has started by position.
+fn is_due(at: usize, position: usize) -> bool {
+    position.wrapping_sub(at) <= usize::max_value() / 2
+}
+
+// A node's view of a ParamRW. The node calls update once per update to see
+// new values and changes, and next for each sample to move toward them.
+#[derive(Debug)]
+pub struct ParamRead {
+    param: ParamRW,
+    current: f32,
+    target: f32,
+    // the param's count of sets last seen
+    sets: usize,
+    ramp: Ramp,
+    // added to or multiplied into current each sample
+    step: f32,
+    // samples left until current reaches target
+    remaining: usize,
+    smoothing: usize,
+    position: usize,
+    samples_per_second: usize,
+    // scheduled changes in the order they start
+    pending: VecDeque<ParamEvent>,
+}
+
+impl ParamRead {
+    // Time scheduled changes in the format's samples instead of 48000 Hz
+    // stereo.
+    pub fn with_format(self, format: StreamFormat) -> ParamRead {
+        ParamRead {
+            samples_per_second: format.rate.unwrap_or(48000) * format.channels.unwrap_or(2),
+            ..self
+        }
+    }
+
+    // Start moving to a value set since the last update, from wherever the
+    // last move got to, and pick up changes scheduled since.
+    pub fn update(&mut self) {
+        self.param.position.store(self.position, Ordering::Relaxed);
+        let sets = self.param.sets.load(Ordering::Acquire);
+        if sets != self.sets {
+            self.sets = sets;
+            let set = self.param.get();
+            let smoothing = self.smoothing;
+            self.move_to(Ramp::Linear, set, smoothing);
+        }
+        // A control thread holding the lock only delays its changes.
+        let events = match self.param.events.try_lock() {
+            Ok(mut events) => events.drain(..).collect::<Vec<ParamEvent>>(),
+            Err(_) => Vec::new(),
+        };
+        for event in events.into_iter() {
+            self.schedule(event);
+        }
+    }
+
+    // Schedule a change on this reader alone, like an envelope a node runs
+    // itself.
+    pub fn schedule(&mut self, event: ParamEvent) {
+        let at = event.at.unwrap_or(self.position);
+        let event = event.at(at);
+        let position = self.position;
+        // Keep pending ordered by how far from now each change starts.
+        let index = self.pending.iter()
+            .position(|other| other.at.unwrap().wrapping_sub(position) > at.wrapping_sub(position))
+            .unwrap_or(self.pending.len());
+        self.pending.insert(index, event);
+    }
+
+    fn move_to(&mut self, ramp: Ramp, target: f32, samples: usize) {
+        self.target = target;
+        // Show control threads where the value is going.
+        self.param.target.store(target.to_bits() as usize, Ordering::Relaxed);
+        self.ramp = ramp;
+        if ramp == Ramp::Step || samples == 0 {
+            self.current = target;
+            self.remaining = 0;
+        }
+        else if ramp == Ramp::Exponential {
+            let from = if self.current == 0.0 {MIN_EXPONENTIAL} else {self.current};
+            let to = if target == 0.0 {if from < 0.0 {-MIN_EXPONENTIAL} else {MIN_EXPONENTIAL}} else {target};
+            if (from < 0.0) != (to < 0.0) {
+                // No ratio moves between signs, so go in a line.
+                self.move_to(Ramp::Linear, target, samples);
+                return;
+            }
+            self.current = from;
+            self.step = (to / from).powf(1.0 / samples as f32);
+            self.remaining = samples;
+        }
+        else {
+            self.step = (target - self.current) / samples as f32;
+            self.remaining = samples;
+        }
+    }
+
+    fn start(&mut self, event: ParamEvent) {
+        let duration = event.duration;
+        let samples = duration.as_secs() as usize * self.samples_per_second +
+            (duration.subsec_nanos() as u64 * self.samples_per_second as u64 / 1_000_000_000) as usize;
+        self.move_to(event.ramp, event.target, samples);
+    }
+
+    // The value for the next sample.
+    pub fn next(&mut self) -> f32 {
+        while self.pending.front().map_or(false, |event| is_due(event.at.unwrap(), self.position)) {
+            let event = self.pending.pop_front().unwrap();
+            self.start(event);
+        }
+        self.position = self.position.wrapping_add(1);
+        if self.remaining > 0 {
+            self.remaining -= 1;
+            self.current = if self.remaining == 0 {
+                self.target
+            }
+            else if self.ramp == Ramp::Exponential {
+                self.current * self.step
+            }
+            else {
+                self.current + self.step
+            };
+        }
+        self.current
+    }
+
+    // The value for the last sample.
+    pub fn get(&self) -> f32 {
+        self.current
+    }
+
+    // The value the current move ends at.
+    pub fn target(&self) -> f32 {
+        self.target
+    }
+
+    // Whether the value has stopped moving.
+    pub fn is_steady(&self) -> bool {
+        self.remaining == 0
+    }
+
+    // Whether the value stays put for the next samples.
+    pub fn is_steady_for(&self, samples: usize) -> bool {
+        self.is_steady() && self.pending.front().map_or(true, |event| {
+            event.at.unwrap().wrapping_sub(self.position) >= samples
+        })
+    }
+
+    // Move past samples without reading each value.
+    pub fn skip(&mut self, samples: usize) {
+        if self.is_steady_for(samples) {
+            self.position = self.position.wrapping_add(samples);
+        }
+        else {
+            for _ in 0..samples {
+                self.next();
+            }
+        }
+    }
+
+    // Jump to the value set, like when the node reading it is reset. Changes
+    // still scheduled start when they are due.
+    pub fn settle(&mut self) {
+        self.update();
+        self.current = self.target;
+        self.remaining = 0;
+    }
+
+    // Write input scaled by the value for each sample into output.
+    pub fn apply<T: Sample>(&mut self, input: &[T], output: &mut [T]) {
+        if self.is_steady_for(input.len()) {
+            let gain = self.current;
+            scale(input, output, gain);
+            self.skip(input.len());
+        }
+        else {
+            for (i, o) in input.iter().zip(output.iter_mut()) {
+                *o = T::from_f32(i.to_f32() * self.next());
+            }
+        }
+    }
+}
+
+// Write input scaled by gain into output.
+pub fn scale<T: Sample>(input: &[T], output: &mut [T], gain: f32) {
+    if gain == 1.0 {
+        let len = min(input.len(), output.len());
+        output[..len].copy_from_slice(&input[..len]);
+        return;
+    }
+    for (i, o) in input.iter().zip(output.iter_mut()) {
+        *o = T::from_f32(i.to_f32() * gain);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use super::{ParamRW, StreamFormat};
+
+    #[test]
+    fn it_reads_across_threads() {
+        let p = ParamRW::new(1.0);
+        let q = p.clone();
+        thread::spawn(move || q.set(0.5)).join().unwrap();
+        assert_eq!(p.get(), 0.5);
+    }
+
+    #[test]
+    fn it_smooths_changes() {
+        let p = ParamRW::new(1.0);
+        let mut read = p.clone_read(4);
+        p.set(0.0);
+        assert_eq!(read.next(), 1.0);
+        read.update();
+        assert_eq!((0..5).map(|_| read.next()).collect::<Vec<f32>>(), vec!(0.75, 0.5, 0.25, 0.0, 0.0));
+        assert!(read.is_steady());
+
+        p.set(2.0);
+        read.update();
+        let mut output = [0i16; 4];
+        read.apply(&[1000, 1000, 1000, 1000], &mut output);
+        assert_eq!(output, [500, 1000, 1500, 2000]);
+    }
+
+    #[test]
+    fn it_changes_at_a_position() {
+        let p = ParamRW::new(1.0);
+        let mut read = p.clone_read(0);
+        read.update();
+        p.set_at(0.0, 2);
+        read.update();
+        let mut output = [0i16; 4];
+        read.apply(&[1000, 1000, 1000, 1000], &mut output);
+        assert_eq!(output, [1000, 1000, 0, 0]);
+        read.update();
+        assert_eq!(p.position(), 4);
+        // The change shows in where the reader is going, not in what was set.
+        assert_eq!(p.target(), 0.0);
+        assert_eq!(p.get(), 1.0);
+
+        // Setting the value from before the change moves back to it.
+        p.set(1.0);
+        read.update();
+        read.apply(&[1000, 1000], &mut output[..2]);
+        assert_eq!(&output[..2], &[1000, 1000]);
+        assert_eq!(p.target(), 1.0);
+    }
+
+    #[test]
+    fn it_ramps_over_a_duration() {
+        let p = ParamRW::new(0.0);
+        // 4 samples a millisecond
+        let mut read = p.clone_read(0).with_format(StreamFormat::new(4000, 1));
+        p.linear_ramp(1.0, Duration::from_millis(1));
+        read.update();
+        assert_eq!((0..5).map(|_| read.next()).collect::<Vec<f32>>(), vec!(0.25, 0.5, 0.75, 1.0, 1.0));
+
+        p.exponential_ramp(0.0625, Duration::from_millis(1));
+        read.update();
+        assert_eq!((0..5).map(|_| read.next()).collect::<Vec<f32>>(), vec!(0.5, 0.25, 0.125, 0.0625, 0.0625));
+        assert!(read.is_steady_for(100));
+    }
+}
@@ -1,32 +1,144 @@
-use super::{Node, RingBuffer, BaseMix};
+use std::any::Any;
+use std::time::Duration;
 
-type PlaybackFn = Box<FnMut(&mut RingBuffer)>;
+use super::{Node, NodeInfo, NodeStatus, RingBuffer, Sample, StreamFormat, BaseMix, is_graph_sample};
 
-pub struct Playback {
+pub type PlaybackFn<T = i16> = Box<FnMut(&mut RingBuffer<T>) + Send>;
+
+pub struct Playback<T: Sample = i16> {
     base_mix: BaseMix,
     tmp_state: Option<RingBuffer>,
-    callback: PlaybackFn,
+    // what the callback plays when T isn't i16
+    converted: Option<RingBuffer<T>>,
+    callback: PlaybackFn<T>,
+    format: StreamFormat,
+    name: Option<String>,
+    latency: Option<Duration>,
+    status: NodeStatus,
 }
 
 impl Playback {
     pub fn new(callback: PlaybackFn) -> Playback {
+        Playback::converting(callback)
+    }
+}
+
+impl<T: Sample> Playback<T> {
+    // A playback reading samples of type T converted from the graph's i16.
+    pub fn converting(callback: PlaybackFn<T>) -> Playback<T> {
         Playback {
             base_mix: BaseMix::new(),
             tmp_state: Some(RingBuffer::new()),
+            converted: if is_graph_sample::<T>() {None} else {Some(RingBuffer::new())},
             callback: callback,
+            format: StreamFormat::any(),
+            name: None,
+            latency: None,
+            status: NodeStatus::Idle,
+        }
+    }
+
+    // The format the device plays, checked against what is connected to it.
+    pub fn with_format(self, format: StreamFormat) -> Playback<T> {
+        Playback {
+            format: format,
+            ..self
+        }
+    }
+
+    // The name of the device it plays, like a card's.
+    pub fn with_name(self, name: &str) -> Playback<T> {
+        Playback {
+            name: Some(name.to_string()),
+            ..self
+        }
+    }
+
+    // How long the device takes to play samples.
+    pub fn with_latency(self, latency: Duration) -> Playback<T> {
+        Playback {
+            latency: Some(latency),
+            ..self
+        }
+    }
+
+    fn node_info(&self) -> NodeInfo {
+        NodeInfo {
+            kind: "playback",
+            name: self.name.clone(),
+            rate: self.format.rate,
+            channels: self.format.channels,
+            latency: self.latency,
+            status: self.status,
+        }
+    }
+
+    fn set_status(&mut self, active: bool) {
+        self.status = if active {NodeStatus::Running} else {NodeStatus::Inactive};
+    }
+
+    // Charge the underruns the callback counted on what it played to the
+    // edges that fell short.
+    fn _count_underruns(&self, inputs: &mut [RingBuffer], underruns: usize) {
+        for input in inputs.iter_mut().filter(|input| input.active) {
+            input.underruns += underruns;
+        }
+    }
+
+    fn _flush(&mut self) {
+        if let Some(ref mut ring) = self.tmp_state {
+            ring.clear();
+        }
+        if let Some(ref mut converted) = self.converted {
+            converted.clear();
         }
     }
 }
 
-impl Node for Playback {
-    fn update(&mut self, inputs: &mut [RingBuffer], _: &mut [RingBuffer]) {
+impl Playback {
+    fn update_samples(&mut self, inputs: &mut [RingBuffer]) {
         let mut ring = self.tmp_state.take().unwrap();
         self.base_mix.mix_inputs_ring(inputs, &mut ring);
+        self.set_status(ring.active);
+        let underruns = ring.underruns;
         (self.callback)(&mut ring);
+        self._count_underruns(inputs, ring.underruns - underruns);
         self.tmp_state = Some(ring);
     }
 }
 
+impl<T: Sample> Node for Playback<T> {
+    fn update(&mut self, inputs: &mut [RingBuffer], _: &mut [RingBuffer]) {
+        if let Some(playback) = (self as &mut Any).downcast_mut::<Playback>() {
+            return playback.update_samples(inputs);
+        }
+        let mut ring = self.tmp_state.take().unwrap();
+        let mut converted = self.converted.take().unwrap();
+        self.base_mix.mix_inputs_ring(inputs, &mut ring);
+        self.set_status(ring.active);
+        converted.active = ring.active;
+        let avail = ring.len();
+        converted.write_converted_from_ring(avail, &mut ring);
+        let underruns = converted.underruns;
+        (self.callback)(&mut converted);
+        self._count_underruns(inputs, converted.underruns - underruns);
+        self.converted = Some(converted);
+        self.tmp_state = Some(ring);
+    }
+
+    fn input_format(&self) -> StreamFormat {
+        self.format
+    }
+
+    fn info(&self) -> NodeInfo {
+        self.node_info()
+    }
+
+    fn flush(&mut self) {
+        self._flush();
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::Playback;
@@ -51,4 +163,19 @@ mod test {
         }
         assert_eq!(inputs[0].len(), 0);
     }
+
+    #[test]
+    fn it_counts_underruns_on_its_inputs() {
+        let mut a = Playback::<f32>::converting(Box::new(|ring| {
+            let mut buffer = Vec::new();
+            ring.read_into(64, &mut buffer);
+        }));
+        let mut inputs = vec!(RingBuffer::new());
+        inputs[0].write_from(48, &vec!(0; 48));
+        {
+            let n = &mut a as &mut Node;
+            n.update(&mut inputs, &mut vec!());
+        }
+        assert_eq!(inputs[0].underruns, 1);
+    }
 }
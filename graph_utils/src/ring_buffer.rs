@@ -1,38 +1,126 @@
 use std::cmp::{min, max};
+use std::collections::VecDeque;
+use std::mem;
 use std::ops::{Index, IndexMut, Range};
 use std::iter::{Chain, Iterator};
 use std::slice;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::Sample;
+
+// What a write does when it doesn't fit in the space left.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    // Overwrite the oldest samples.
+    DropOldest,
+    // Write only what fits.
+    DropNewest,
+    // Throw away everything buffered and start again from the write.
+    Resync,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> OverflowPolicy {
+        OverflowPolicy::DropOldest
+    }
+}
+
+// When a sample was captured, attached to it so wherever it is played can
+// tell how long it took to get there.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeMark {
+    // The capture that made the mark, to tell paths apart once they're mixed.
+    pub source: Arc<str>,
+    pub time: Instant,
+}
+
+impl TimeMark {
+    pub fn new<S: Into<Arc<str>>>(source: S, time: Instant) -> TimeMark {
+        TimeMark {
+            source: source.into(),
+            time: time,
+        }
+    }
+
+    pub fn now<S: Into<Arc<str>>>(source: S) -> TimeMark {
+        TimeMark::new(source, Instant::now())
+    }
+
+    // How long ago the marked sample was captured.
+    pub fn latency(&self, now: Instant) -> Duration {
+        now.duration_since(self.time)
+    }
+}
+
+// One reader's place in a ring read by several readers, like the edges fanning
+// out of one port. The ring holds samples from its slowest reader on.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ReadCursor {
+    pub position: u64,
+    pub to_port: usize,
+    pub underruns: usize,
+}
+
+impl ReadCursor {
+    // Move to where reader, made by RingBuffer::reader, stopped.
+    pub fn follow<T: Sample>(&mut self, reader: &RingBuffer<T>) {
+        self.position = reader.read_position();
+        self.underruns = reader.underruns;
+    }
+}
 
 #[derive(Debug)]
-pub struct RingBuffer {
+pub struct RingBuffer<T: Sample = i16> {
     pub max_length: usize,
     pub active: bool,
+    // Port indices of the edge this buffer carries.
+    pub from_port: usize,
+    pub to_port: usize,
+    pub overflow: OverflowPolicy,
+    // Writes that didn't fit and reads asking for more than was buffered.
+    pub overruns: usize,
+    pub underruns: usize,
     start_index: usize,
     end_index: usize,
-    pub buffer: Vec<i16>,
+    // Samples ever written, and marks by the position of their sample in
+    // that count.
+    written: u64,
+    // Readers share the marks and samples with the ring they read. Writing
+    // to either copies them first if they're still shared.
+    marks: Arc<VecDeque<(u64, TimeMark)>>,
+    pub buffer: Arc<Vec<T>>,
 }
 
-impl Default for RingBuffer {
-    fn default() -> RingBuffer {
+impl<T: Sample> Default for RingBuffer<T> {
+    fn default() -> RingBuffer<T> {
         RingBuffer {
             max_length: 32768,
             active: true,
+            from_port: 0,
+            to_port: 0,
+            overflow: Default::default(),
+            overruns: 0,
+            underruns: 0,
             start_index: 0,
             end_index: 0,
-            buffer: Vec::<i16>::new(),
+            written: 0,
+            marks: Arc::new(VecDeque::new()),
+            buffer: Arc::new(Vec::<T>::new()),
         }
     }
 }
 
-impl RingBuffer {
-    pub fn new() -> RingBuffer {
+impl<T: Sample> RingBuffer<T> {
+    pub fn new() -> RingBuffer<T> {
         RingBuffer { ..Default::default() }
     }
 
-    pub fn from(buffer: Vec<i16>) -> RingBuffer {
+    pub fn from(buffer: Vec<T>) -> RingBuffer<T> {
         RingBuffer {
             end_index: buffer.len(),
-            buffer: buffer,
+            written: buffer.len() as u64,
+            buffer: Arc::new(buffer),
             .. Default::default()
         }
     }
@@ -48,13 +136,128 @@ impl RingBuffer {
         }
     }
 
+    // Whole frames of interleaved samples buffered.
+    pub fn frames(&self, channels: usize) -> usize {
+        self.len() / channels
+    }
+
     pub fn clear(&mut self) {
         self.start_index = 0;
         self.end_index = 0;
+        Arc::make_mut(&mut self.marks).clear();
     }
 
-    pub fn read_slice<'a>(&'a mut self, amount: usize) -> RingSlice<'a> {
+    // The samples written since the ring was made. Marks are placed by
+    // position in this count.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    // The position of the next sample read.
+    pub fn read_position(&self) -> u64 {
+        self.written.wrapping_sub(self.len() as u64)
+    }
+
+    // Move the next read to position. Positions before the first sample
+    // still buffered have been overwritten, so position must be between it
+    // and written.
+    pub fn seek(&mut self, position: u64) {
+        assert!(position <= self.written);
+        let behind = (self.written - position) as usize;
+        assert!(behind <= self.max_length);
+        self.start_index = (self.end_index + self.max_length + 1 - behind) % (self.max_length + 1);
+    }
+
+    // A reader of this ring starting with the next sample written.
+    pub fn cursor(&self, to_port: usize) -> ReadCursor {
+        ReadCursor {
+            position: self.written,
+            to_port: to_port,
+            underruns: 0,
+        }
+    }
+
+    // Read from cursor's place, keeping where this was reading in cursor.
+    // Swapping again after reading moves cursor to where the read stopped.
+    pub fn swap_cursor(&mut self, cursor: &mut ReadCursor) {
+        let position = self.read_position();
+        self.seek(cursor.position);
+        cursor.position = position;
+        mem::swap(&mut self.to_port, &mut cursor.to_port);
+        mem::swap(&mut self.underruns, &mut cursor.underruns);
+    }
+
+    // A ring reading this ring's samples from cursor's place without copying
+    // them, so readers of one ring can read it at the same time. Follow the
+    // reader with cursor once it's done.
+    pub fn reader(&self, cursor: &ReadCursor) -> RingBuffer<T> {
+        let mut reader = RingBuffer {
+            max_length: self.max_length,
+            active: self.active,
+            from_port: self.from_port,
+            to_port: cursor.to_port,
+            overflow: self.overflow,
+            overruns: self.overruns,
+            underruns: cursor.underruns,
+            start_index: self.start_index,
+            end_index: self.end_index,
+            written: self.written,
+            marks: self.marks.clone(),
+            buffer: self.buffer.clone(),
+        };
+        reader.seek(cursor.position);
+        reader
+    }
+
+    // Attach mark to the sample at position, whether it's written yet or
+    // not.
+    pub fn mark(&mut self, position: u64, mark: TimeMark) {
+        let index = self.marks.iter().rposition(|&(p, _)| p <= position).map_or(0, |i| i + 1);
+        Arc::make_mut(&mut self.marks).insert(index, (position, mark));
+    }
+
+    // Add the marks on the next amount samples to be read to marks, with
+    // their offset from the first of those samples.
+    pub fn peek_marks(&self, amount: usize, marks: &mut Vec<(usize, TimeMark)>) {
+        let start = self.read_position();
+        let amount = min(amount, self.len()) as u64;
+        for &(position, ref mark) in self.marks.iter() {
+            let offset = position.wrapping_sub(start);
+            if offset < amount {
+                marks.push((offset as usize, mark.clone()));
+            }
+        }
+    }
+
+    // Carry the marks on the next amount samples to be read over to the
+    // samples ring writes next.
+    pub fn forward_marks<S: Sample>(&self, amount: usize, ring: &mut RingBuffer<S>) {
+        let start = self.read_position();
+        let amount = min(amount, self.len()) as u64;
+        let to = ring.written;
+        for &(position, ref mark) in self.marks.iter() {
+            let offset = position.wrapping_sub(start);
+            if offset < amount {
+                ring.mark(to + offset, mark.clone());
+            }
+        }
+    }
+
+    // Drop marks on samples that have been read or overwritten. Marks are only
+    // dropped when writing, as a ring shared by readers is then read from its
+    // slowest reader.
+    fn _prune_marks(&mut self) {
+        let start = self.read_position();
+        while self.marks.front().map_or(false, |&(position, _)| position < start) {
+            Arc::make_mut(&mut self.marks).pop_front();
+        }
+    }
+
+    pub fn read_slice<'a>(&'a mut self, amount: usize) -> RingSlice<'a, T> {
         let avail = min(amount, self.len());
+        if amount > avail {
+            self.underruns += 1;
+        }
         let start_index = self.start_index;
 
         self._bump_start(avail);
@@ -66,11 +269,14 @@ impl RingBuffer {
         }
     }
 
-    pub fn read_into(&mut self, amount: usize, buffer: &mut Vec<i16>) -> usize {
+    pub fn read_into(&mut self, amount: usize, buffer: &mut Vec<T>) -> usize {
         assert!(amount >= 0);
         let amount_avail = min(amount, self.len());
+        if amount > amount_avail {
+            self.underruns += 1;
+        }
         for _ in (buffer.len())..amount_avail {
-            buffer.push(0);
+            buffer.push(T::default());
         }
         for (o, i) in buffer.iter_mut().zip(self.read_slice(amount_avail).iter()) {
             *o = *i;
@@ -82,14 +288,26 @@ impl RingBuffer {
         amount_avail
     }
 
-    pub fn write_slice<'a>(&'a mut self, amount: usize) -> RingSlice<'a> {
-        let avail = min(amount, self.max_length);
+    pub fn write_slice<'a>(&'a mut self, amount: usize) -> RingSlice<'a, T> {
+        let mut avail = min(amount, self.max_length);
+        let free = self.max_length - self.len();
+        if amount > free {
+            self.overruns += 1;
+            match self.overflow {
+                OverflowPolicy::DropOldest => {},
+                OverflowPolicy::DropNewest => avail = free,
+                OverflowPolicy::Resync => self.clear(),
+            }
+        }
         let end_index = self.end_index;
 
         self._bump_end(avail);
+        self.written += avail as u64;
+        self._prune_marks();
 
-        for _ in self.buffer.len()..min(end_index + avail + 1, self.max_length + 1) {
-            self.buffer.push(0);
+        let buffer = Arc::make_mut(&mut self.buffer);
+        for _ in buffer.len()..min(end_index + avail + 1, self.max_length + 1) {
+            buffer.push(T::default());
         }
 
         RingSlice {
@@ -99,7 +317,7 @@ impl RingBuffer {
         }
     }
 
-    fn _write_from<'a, T>(&mut self, amount: usize, buffer: T) -> usize where T : Iterator<Item=&'a i16> {
+    fn _write_from(&mut self, amount: usize, buffer: (&[T], &[T])) -> usize {
         assert!(amount >= 0);
         let amount_avail = min(amount, self.max_length);
         // for _ in self.buffer.len()..min(self.end_index + amount_avail + 1, self.max_length + 1) {
@@ -108,21 +326,20 @@ impl RingBuffer {
         // for (i, j) in (0..amount_avail).zip(self._end_range_iter(amount_avail)) {
         //     self.buffer[j] = buffer[i];
         // }
-        for (i, o) in buffer.zip(self.write_slice(amount_avail).iter_mut()) {
-            *o = *i;
-        }
+        let mut slice = self.write_slice(amount_avail);
+        zip_runs(buffer, slice.as_mut_slices(), |i, o| o.copy_from_slice(i));
 
         // self._bump_end(amount_avail);
 
-        amount_avail
+        slice.len()
     }
 
-    pub fn write_from(&mut self, amount: usize, buffer: &Vec<i16>) -> usize {
-        self._write_from(min(amount, buffer.len()), buffer.iter())
+    pub fn write_from(&mut self, amount: usize, buffer: &Vec<T>) -> usize {
+        self._write_from(min(amount, buffer.len()), (&buffer[..], &[]))
     }
 
-    pub fn write_from_read_slice(&mut self, amount: usize, buffer: &RingSlice) -> usize {
-        self._write_from(min(amount, buffer.len()), buffer.iter())
+    pub fn write_from_read_slice(&mut self, amount: usize, buffer: &RingSlice<T>) -> usize {
+        self._write_from(min(amount, buffer.len()), buffer.as_slices())
     }
 
     fn _range_iter(start: usize, len: usize, max_len: usize) -> Chain<Range<usize>, Range<usize>> {
@@ -131,11 +348,11 @@ impl RingBuffer {
     }
 
     fn _start_range_iter(&self, len: usize) -> Chain<Range<usize>, Range<usize>> {
-        RingBuffer::_range_iter(self.start_index, len, self.max_length)
+        RingBuffer::<T>::_range_iter(self.start_index, len, self.max_length)
     }
 
     fn _end_range_iter(&self, len: usize) -> Chain<Range<usize>, Range<usize>> {
-        RingBuffer::_range_iter(self.end_index, len, self.max_length)
+        RingBuffer::<T>::_range_iter(self.end_index, len, self.max_length)
     }
 
     fn _bump_start(&mut self, len: usize) {
@@ -149,14 +366,14 @@ impl RingBuffer {
         if self.end_index < self.start_index && self.end_index + len >= self.start_index {
             self.start_index = self.end_index + len + 1;
             if self.start_index > self.max_length {
-                self.start_index -= self.max_length - 1;
+                self.start_index -= self.max_length + 1;
             }
         }
         else if self.end_index > self.start_index &&
             self.end_index + len > self.max_length &&
             self.end_index + len - self.max_length >= self.start_index
         {
-            self.start_index = self.end_index + len - self.max_length + 1;
+            self.start_index = self.end_index + len - self.max_length;
         }
         self.end_index += len;
         if self.end_index > self.max_length {
@@ -164,7 +381,7 @@ impl RingBuffer {
         }
     }
 
-    pub fn write_from_ring(&mut self, amount: usize, ring: &mut RingBuffer) -> usize {
+    pub fn write_from_ring(&mut self, amount: usize, ring: &mut RingBuffer<T>) -> usize {
         assert!(amount >= 0);
         let amount_avail = min(min(amount, ring.len()), self.max_length);
         // for _ in self.buffer.len()..min(self.end_index + amount_avail + 1, self.max_length + 1) {
@@ -173,21 +390,57 @@ impl RingBuffer {
         // for (i, j) in ring._start_range_iter(amount_avail).zip(self._end_range_iter(amount_avail)) {
         //     self.buffer[j] = ring.buffer[i];
         // }
-        for (i, o) in ring.read_slice(amount_avail).iter().zip(self.write_slice(amount_avail).iter_mut()) {
-            *o = *i;
-        }
+        ring.forward_marks(amount_avail, self);
+        ring.read_slice(amount_avail).zip_runs(&mut self.write_slice(amount_avail), |i, o| o.copy_from_slice(i));
 
         // self._bump_end(amount_avail);
         // ring._bump_start(amount_avail);
 
         amount_avail
     }
+
+    // Read samples of another type out of ring, converting them as they are
+    // written.
+    pub fn write_converted_from_ring<S: Sample>(&mut self, amount: usize, ring: &mut RingBuffer<S>) -> usize {
+        let amount_avail = min(min(amount, ring.len()), self.max_length);
+        ring.forward_marks(amount_avail, self);
+        ring.read_slice(amount_avail).zip_runs(&mut self.write_slice(amount_avail), |i, o| {
+            for (i, o) in i.iter().zip(o.iter_mut()) {
+                *o = i.convert::<T>();
+            }
+        });
+        amount_avail
+    }
+}
+
+// Call f with runs of input and output of the same length, in order, until
+// either runs out. Each side is the (up to) two runs a RingSlice is stored in.
+pub fn zip_runs<T, S, F>(input: (&[T], &[T]), output: (&mut [S], &mut [S]), mut f: F) where F: FnMut(&[T], &mut [S]) {
+    let (mut a, mut next_a) = input;
+    let (mut b, mut next_b) = output;
+    loop {
+        if a.len() == 0 {
+            a = mem::replace(&mut next_a, &[]);
+        }
+        if b.len() == 0 {
+            b = mem::replace(&mut next_b, &mut []);
+        }
+        let len = min(a.len(), b.len());
+        if len == 0 {
+            break;
+        }
+        let (a_run, a_rest) = a.split_at(len);
+        let (b_run, b_rest) = mem::replace(&mut b, &mut []).split_at_mut(len);
+        f(a_run, b_run);
+        a = a_rest;
+        b = b_rest;
+    }
 }
 
-pub struct RingSlice<'a> {
+pub struct RingSlice<'a, T: 'a + Sample = i16> {
     _len: usize,
     start: usize,
-    ring: &'a mut RingBuffer,
+    ring: &'a mut RingBuffer<T>,
 }
 
 // pub struct RingSliceIter<'a> {
@@ -224,35 +477,52 @@ pub struct RingSlice<'a> {
 //     }
 // }
 
-impl<'a> RingSlice<'a> {
+impl<'a, T: Sample> RingSlice<'a, T> {
     pub fn len(&self) -> usize {
         self._len
     }
 
-    pub fn iter<'b>(&'b self) -> Chain<slice::Iter<'b, i16>, slice::Iter<'b, i16>> {
+    // The samples as the (up to) two runs they're stored in. The second is
+    // empty unless the slice wraps around the end of the ring. Loops over
+    // them skip the wrap around check indexing does for each sample.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
         let start = self.start;
         let len = self._len;
         let max_len = self.ring.max_length;
 
-        self.ring.buffer[start..min(start + len, max_len + 1)].iter()
-        .chain(self.ring.buffer[0..max((start + len) as i32 - (max_len + 1) as i32, 0) as usize].iter())
+        (&self.ring.buffer[start..min(start + len, max_len + 1)],
+            &self.ring.buffer[0..max((start + len) as i32 - (max_len + 1) as i32, 0) as usize])
     }
 
-    pub fn iter_mut<'b>(&'b mut self) -> Chain<slice::IterMut<'b, i16>, slice::IterMut<'b, i16>> {
+    pub fn as_mut_slices(&mut self) -> (&mut [T], &mut [T]) {
         let start = self.start;
         let len = self._len;
         let max_len = self.ring.max_length;
 
-        unsafe {
-            let buffer_ptr = self.ring.buffer.as_mut_ptr();
-            slice::from_raw_parts_mut(buffer_ptr.offset(start as isize), min(start + len, max_len + 1) - start).iter_mut()
-            .chain(slice::from_raw_parts_mut(buffer_ptr, max((start + len) as i32 - (max_len + 1) as i32, 0) as usize).iter_mut())
-        }
+        let (head, tail) = Arc::make_mut(&mut self.ring.buffer).split_at_mut(start);
+        (&mut tail[..min(start + len, max_len + 1) - start],
+            &mut head[..max((start + len) as i32 - (max_len + 1) as i32, 0) as usize])
+    }
+
+    // Call f with runs of this slice and out of the same length. See
+    // zip_runs.
+    pub fn zip_runs<S: Sample, F>(&self, out: &mut RingSlice<S>, f: F) where F: FnMut(&[T], &mut [S]) {
+        zip_runs(self.as_slices(), out.as_mut_slices(), f);
+    }
+
+    pub fn iter<'b>(&'b self) -> Chain<slice::Iter<'b, T>, slice::Iter<'b, T>> {
+        let (first, second) = self.as_slices();
+        first.iter().chain(second.iter())
+    }
+
+    pub fn iter_mut<'b>(&'b mut self) -> Chain<slice::IterMut<'b, T>, slice::IterMut<'b, T>> {
+        let (first, second) = self.as_mut_slices();
+        first.iter_mut().chain(second.iter_mut())
     }
 }
 
-impl<'a> Index<usize> for RingSlice<'a> {
-    type Output = i16;
+impl<'a, T: Sample> Index<usize> for RingSlice<'a, T> {
+    type Output = T;
     fn index(&self, mut index: usize) -> &Self::Output {
         assert!(index < self._len);
         index += self.start;
@@ -263,19 +533,22 @@ impl<'a> Index<usize> for RingSlice<'a> {
     }
 }
 
-impl<'a> IndexMut<usize> for RingSlice<'a> {
+impl<'a, T: Sample> IndexMut<usize> for RingSlice<'a, T> {
     fn index_mut(&mut self, mut index: usize) -> &mut Self::Output {
         index += self.start;
         if index > self.ring.max_length {
             index -= self.ring.max_length + 1;
         }
-        &mut self.ring.buffer[index]
+        &mut Arc::make_mut(&mut self.ring.buffer)[index]
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::RingBuffer;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use super::{RingBuffer, OverflowPolicy, TimeMark};
 
     #[test]
     fn it_reads_into() {
@@ -283,7 +556,7 @@ mod tests {
         let mut v = Vec::<i16>::new();
 
         for i in 0..48 {
-            a.buffer.push(i as i16);
+            Arc::make_mut(&mut a.buffer).push(i as i16);
         }
         a.end_index = 48;
 
@@ -338,7 +611,7 @@ mod tests {
         let mut v = Vec::<i16>::new();
 
         for i in 0..49 {
-            a.buffer.push(i as i16);
+            Arc::make_mut(&mut a.buffer).push(i as i16);
         }
         a.max_length = 48;
         a.start_index = 24;
@@ -437,4 +710,136 @@ mod tests {
         assert_eq!(a.write_slice(47).iter_mut().count(), 47);
         assert_eq!(a.read_slice(47).iter_mut().count(), 47);
     }
+
+    #[test]
+    fn it_counts_overflows() {
+        let v = (0..32).map(|i| i as i16).collect::<Vec<i16>>();
+        let mut read = Vec::<i16>::new();
+
+        let mut a = RingBuffer::new();
+        a.max_length = 48;
+        a.write_from(32, &v);
+        assert_eq!(a.write_from(32, &v), 32);
+        assert_eq!((a.len(), a.overruns), (48, 1));
+        a.read_into(1, &mut read);
+        assert_eq!(read[0], 16);
+
+        let mut a = RingBuffer::new();
+        a.max_length = 48;
+        a.overflow = OverflowPolicy::DropNewest;
+        a.write_from(32, &v);
+        assert_eq!(a.write_from(32, &v), 16);
+        assert_eq!((a.len(), a.overruns), (48, 1));
+        a.read_into(1, &mut read);
+        assert_eq!(read[0], 0);
+
+        let mut a = RingBuffer::new();
+        a.max_length = 48;
+        a.overflow = OverflowPolicy::Resync;
+        a.write_from(32, &v);
+        a.write_from(32, &v);
+        assert_eq!((a.len(), a.overruns), (32, 1));
+
+        assert_eq!(a.read_into(48, &mut read), 32);
+        assert_eq!(a.read_slice(1).len(), 0);
+        assert_eq!(a.underruns, 2);
+    }
+
+    #[test]
+    fn it_converts_between_rings() {
+        let mut a = RingBuffer::<f32>::new();
+        a.write_from(2, &vec!(0.5, -0.25));
+        let mut b = RingBuffer::<i16>::new();
+        assert_eq!(b.write_converted_from_ring(3, &mut a), 2);
+        assert_eq!(b.read_slice(2).iter().cloned().collect::<Vec<i16>>(), vec!(16384, -8192));
+    }
+
+    #[test]
+    fn it_carries_marks() {
+        let mark = TimeMark::new("a", Instant::now());
+        let v = (0..32).map(|i| i as i16).collect::<Vec<i16>>();
+        let mut a = RingBuffer::new();
+        a.write_from(32, &v);
+        a.mark(10, mark.clone());
+        a.read_slice(8);
+
+        let mut marks = Vec::new();
+        a.peek_marks(2, &mut marks);
+        assert_eq!(marks, vec!());
+        a.peek_marks(24, &mut marks);
+        assert_eq!(marks, vec!((2, mark.clone())));
+
+        let mut b = RingBuffer::<f32>::new();
+        b.write_from(5, &vec!(0.0; 5));
+        b.write_converted_from_ring(16, &mut a);
+        let mut c = RingBuffer::<i16>::new();
+        c.write_converted_from_ring(9, &mut b);
+        marks.clear();
+        a.peek_marks(8, &mut marks);
+        b.peek_marks(12, &mut marks);
+        c.peek_marks(9, &mut marks);
+        assert_eq!(marks, vec!((7, mark)));
+
+        c.read_slice(8);
+        marks.clear();
+        c.peek_marks(1, &mut marks);
+        assert_eq!(marks, vec!());
+    }
+
+    #[test]
+    fn it_reads_from_cursors() {
+        let v = (0..32).map(|i| i as i16).collect::<Vec<i16>>();
+        let mut a = RingBuffer::new();
+        a.max_length = 48;
+        let mut fast = a.cursor(1);
+        let mut slow = a.cursor(2);
+        a.write_from(32, &v);
+
+        a.swap_cursor(&mut fast);
+        assert_eq!(a.to_port, 1);
+        assert_eq!(a.read_slice(24)[0], 0);
+        a.swap_cursor(&mut fast);
+        assert_eq!((fast.position, a.len()), (24, 32));
+
+        // The slow reader still holds the first samples back.
+        assert_eq!(a.write_from(16, &v), 16);
+        assert_eq!((a.len(), a.overruns), (48, 0));
+        a.swap_cursor(&mut slow);
+        assert_eq!(a.read_slice(1)[0], 0);
+        a.swap_cursor(&mut slow);
+        a.swap_cursor(&mut fast);
+        assert_eq!(a.read_slice(48).len(), 24);
+        assert_eq!(a.underruns, 1);
+        a.swap_cursor(&mut fast);
+        assert_eq!((fast.underruns, a.underruns), (1, 0));
+        assert_eq!(a.to_port, 0);
+    }
+
+    #[test]
+    fn it_splits_slices_into_runs() {
+        let mut a = RingBuffer::new();
+        a.max_length = 48;
+        a.write_from(40, &(0..40).collect::<Vec<i16>>());
+        a.read_slice(30);
+        a.write_from(20, &(40..60).collect::<Vec<i16>>());
+        {
+            let slice = a.read_slice(30);
+            let (first, second) = slice.as_slices();
+            assert_eq!((first.len(), second.len()), (19, 11));
+            assert_eq!((first[0], second[0]), (30, 49));
+        }
+
+        let mut b = RingBuffer::new();
+        b.max_length = 48;
+        b.write_from(20, &vec!(0; 20));
+        b.read_slice(20);
+        let mut runs = Vec::new();
+        a.write_from(30, &(0..30).collect::<Vec<i16>>());
+        a.read_slice(30).zip_runs(&mut b.write_slice(30), |i, o| {
+            runs.push(i.len());
+            o.copy_from_slice(i);
+        });
+        assert_eq!(runs, vec!(29, 1));
+        assert_eq!(b.read_slice(30).iter().cloned().collect::<Vec<i16>>(), (0..30).collect::<Vec<i16>>());
+    }
 }
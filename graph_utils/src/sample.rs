@@ -0,0 +1,92 @@
+use std::any::TypeId;
+use std::fmt::Debug;
+use std::{i16, i32};
+
+// A type samples can be stored as. Conversions go through f32 where full
+// scale is -1.0 to 1.0.
+pub trait Sample : Copy + Default + PartialOrd + Debug + Send + Sync + 'static {
+    fn to_f32(self) -> f32;
+    fn from_f32(value: f32) -> Self;
+    // Add two samples, clamping integer samples to their range instead of
+    // wrapping.
+    fn mix(self, other: Self) -> Self;
+
+    fn convert<S: Sample>(self) -> S {
+        S::from_f32(self.to_f32())
+    }
+}
+
+// Whether S is the graph's own i16 samples, so nodes of S don't need to
+// convert what they read and write.
+pub fn is_graph_sample<S: Sample>() -> bool {
+    TypeId::of::<S>() == TypeId::of::<i16>()
+}
+
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / 32768.0
+    }
+
+    fn from_f32(value: f32) -> i16 {
+        (value * 32768.0).max(i16::MIN as f32).min(i16::MAX as f32) as i16
+    }
+
+    fn mix(self, other: i16) -> i16 {
+        self.saturating_add(other)
+    }
+}
+
+impl Sample for i32 {
+    fn to_f32(self) -> f32 {
+        (self as f64 / 2147483648.0) as f32
+    }
+
+    fn from_f32(value: f32) -> i32 {
+        (value as f64 * 2147483648.0).max(i32::MIN as f64).min(i32::MAX as f64) as i32
+    }
+
+    fn mix(self, other: i32) -> i32 {
+        self.saturating_add(other)
+    }
+}
+
+impl Sample for f32 {
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn from_f32(value: f32) -> f32 {
+        value
+    }
+
+    fn mix(self, other: f32) -> f32 {
+        self + other
+    }
+}
+
+// Convert a run of samples, like captured 24 bit frames into i16 for the
+// graph.
+pub fn convert_samples<S: Sample, T: Sample>(input: &[S], output: &mut Vec<T>) {
+    output.clear();
+    output.extend(input.iter().map(|&sample| sample.convert::<T>()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Sample, convert_samples};
+
+    #[test]
+    fn it_converts_samples() {
+        assert_eq!(16384i16.to_f32(), 0.5);
+        assert_eq!(i16::from_f32(-1.0), -32768);
+        assert_eq!(i16::from_f32(2.0), 32767);
+        assert_eq!(256i16.convert::<i32>(), 256 << 16);
+        assert_eq!((256i32 << 16).convert::<i16>(), 256);
+        assert_eq!(30000i16.mix(30000), 32767);
+        assert_eq!(0.75f32.mix(0.5), 1.25);
+
+        let mut output = Vec::<f32>::new();
+        convert_samples(&[-16384i16, 0, 8192], &mut output);
+        assert_eq!(output, vec!(-0.5, 0.0, 0.25));
+    }
+}
@@ -0,0 +1,214 @@
+use std::cell::UnsafeCell;
+use std::cmp::min;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::{RingBuffer, Sample};
+
+// Samples handed from one thread to another without locking. The producer
+// only moves tail and the consumer only moves head, so neither ever waits on
+// the other.
+struct Shared<T> {
+    // one more slot than the capacity so a full ring isn't an empty one
+    buffer: Vec<UnsafeCell<T>>,
+    // next slot the consumer reads
+    head: AtomicUsize,
+    // next slot the producer writes
+    tail: AtomicUsize,
+    // set by the producer to have the consumer drop what it hasn't read
+    discard: AtomicBool,
+}
+
+unsafe impl<T: Send> Sync for Shared<T> {}
+
+impl<T> Shared<T> {
+    fn len(&self, head: usize, tail: usize) -> usize {
+        (tail + self.buffer.len() - head) % self.buffer.len()
+    }
+}
+
+// The writing half of a spsc_ring, for a reader thread like a socket's.
+pub struct RingProducer<T: Sample = i16> {
+    shared: Arc<Shared<T>>,
+}
+
+// The reading half of a spsc_ring, for the graph thread.
+pub struct RingConsumer<T: Sample = i16> {
+    shared: Arc<Shared<T>>,
+}
+
+// A single producer, single consumer ring holding up to capacity samples.
+pub fn spsc_ring<T: Sample>(capacity: usize) -> (RingProducer<T>, RingConsumer<T>) {
+    let shared = Arc::new(Shared {
+        buffer: (0..capacity + 1).map(|_| UnsafeCell::new(T::default())).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        discard: AtomicBool::new(false),
+    });
+    (RingProducer {shared: shared.clone()}, RingConsumer {shared: shared})
+}
+
+impl<T: Sample> RingProducer<T> {
+    pub fn capacity(&self) -> usize {
+        self.shared.buffer.len() - 1
+    }
+
+    // Samples written and not yet read.
+    pub fn len(&self) -> usize {
+        let head = self.shared.head.load(Ordering::Acquire);
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        self.shared.len(head, tail)
+    }
+
+    // Write as many samples as fit, returning how many did.
+    pub fn write(&mut self, samples: &[T]) -> usize {
+        let head = self.shared.head.load(Ordering::Acquire);
+        let tail = self.shared.tail.load(Ordering::Relaxed);
+        let size = self.shared.buffer.len();
+        let amount = min(samples.len(), self.capacity() - self.shared.len(head, tail));
+        for (i, &sample) in samples[..amount].iter().enumerate() {
+            unsafe {
+                *self.shared.buffer[(tail + i) % size].get() = sample;
+            }
+        }
+        self.shared.tail.store((tail + amount) % size, Ordering::Release);
+        amount
+    }
+
+    // Have the consumer drop everything it hasn't read the next time it
+    // looks at the ring.
+    pub fn discard(&mut self) {
+        self.shared.discard.store(true, Ordering::Release);
+    }
+
+    // Whether a discard is waiting for the consumer to look at the ring.
+    pub fn discarding(&self) -> bool {
+        self.shared.discard.load(Ordering::Acquire)
+    }
+}
+
+impl<T: Sample> RingConsumer<T> {
+    pub fn len(&mut self) -> usize {
+        if self.shared.discard.swap(false, Ordering::Acquire) {
+            self.clear();
+        }
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        self.shared.len(head, tail)
+    }
+
+    pub fn clear(&mut self) {
+        let tail = self.shared.tail.load(Ordering::Acquire);
+        self.shared.head.store(tail, Ordering::Release);
+    }
+
+    // Read up to amount samples into ring, returning how many were read.
+    // Samples ring has no room for stay in this one.
+    pub fn read_into_ring(&mut self, amount: usize, ring: &mut RingBuffer<T>) -> usize {
+        let amount = min(amount, self.len());
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let size = self.shared.buffer.len();
+        let mut slice = ring.write_slice(amount);
+        let read = slice.len();
+        for (i, o) in slice.iter_mut().enumerate() {
+            *o = unsafe { *self.shared.buffer[(head + i) % size].get() };
+        }
+        self.shared.head.store((head + read) % size, Ordering::Release);
+        read
+    }
+
+    pub fn read(&mut self, samples: &mut [T]) -> usize {
+        let amount = min(samples.len(), self.len());
+        let head = self.shared.head.load(Ordering::Relaxed);
+        let size = self.shared.buffer.len();
+        for (i, o) in samples[..amount].iter_mut().enumerate() {
+            *o = unsafe { *self.shared.buffer[(head + i) % size].get() };
+        }
+        self.shared.head.store((head + amount) % size, Ordering::Release);
+        amount
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cmp::min;
+    use std::thread;
+
+    use super::spsc_ring;
+    use super::super::{OverflowPolicy, RingBuffer};
+
+    #[test]
+    fn it_hands_samples_across_threads() {
+        let (mut producer, mut consumer) = spsc_ring::<i32>(64);
+        let total = 100000;
+        let writer = thread::spawn(move || {
+            let samples = (0..total as i32).collect::<Vec<i32>>();
+            let mut next = 0;
+            while next < total {
+                let written = producer.write(&samples[next..min(next + 37, total)]);
+                if written == 0 {
+                    thread::yield_now();
+                }
+                next += written;
+            }
+        });
+
+        let mut ring = RingBuffer::new();
+        let mut expected = 0;
+        let mut read = vec![0; 29];
+        while expected < total {
+            // Alternate how the consumer reads to cover both paths.
+            let amount = if expected % 2 == 0 {
+                consumer.read(&mut read)
+            }
+            else {
+                let amount = consumer.read_into_ring(29, &mut ring);
+                ring.read_into(amount, &mut read);
+                amount
+            };
+            if amount == 0 {
+                thread::yield_now();
+            }
+            for &sample in read[..amount].iter() {
+                assert_eq!(sample, expected as i32);
+                expected += 1;
+            }
+        }
+        writer.join().unwrap();
+        assert_eq!(consumer.len(), 0);
+    }
+
+    #[test]
+    fn it_discards_unread_samples() {
+        let (mut producer, mut consumer) = spsc_ring::<i16>(4);
+        assert_eq!(producer.write(&[1, 2, 3, 4, 5]), 4);
+        assert_eq!(producer.len(), 4);
+        producer.discard();
+        assert!(producer.discarding());
+        assert_eq!(consumer.len(), 0);
+        assert!(!producer.discarding());
+        assert_eq!(producer.write(&[6, 7]), 2);
+        let mut read = [0; 4];
+        assert_eq!(consumer.read(&mut read), 2);
+        assert_eq!(&read[..2], &[6, 7]);
+    }
+
+    #[test]
+    fn it_keeps_what_a_full_ring_drops() {
+        let (mut producer, mut consumer) = spsc_ring::<i16>(8);
+        assert_eq!(producer.write(&[1, 2, 3, 4, 5, 6]), 6);
+        let mut ring = RingBuffer::new();
+        ring.max_length = 4;
+        ring.overflow = OverflowPolicy::DropNewest;
+        ring.write_from(2, &vec!(0, 0));
+
+        assert_eq!(consumer.read_into_ring(6, &mut ring), 2);
+        assert_eq!(consumer.len(), 4);
+        let mut read = [0; 4];
+        assert_eq!(consumer.read(&mut read), 4);
+        assert_eq!(read, [3, 4, 5, 6]);
+        let mut samples = Vec::new();
+        ring.read_into(4, &mut samples);
+        assert_eq!(samples, vec!(0, 0, 1, 2));
+    }
+}
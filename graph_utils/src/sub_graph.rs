@@ -0,0 +1,174 @@
+use super::{Node, NodeInfo, RingBuffer, BaseMix, Graph, GraphNodeParams, NodeId, copy_out_ring};
+
+// Where a SubGraph's inputs enter its inner graph.
+pub struct GraphInput {
+    ring: RingBuffer,
+    buffer: Vec<i16>,
+}
+
+// Where a SubGraph's outputs leave its inner graph.
+pub struct GraphOutput {
+    base_mix: BaseMix,
+    ring: RingBuffer,
+}
+
+// A graph that can be connected to another graph like any other node. Nodes
+// connected from input_id receive the sub graph's mixed inputs and nodes
+// connected to output_id are mixed into its outputs.
+pub struct SubGraph {
+    graph: Graph,
+    input_id: NodeId<GraphInput>,
+    output_id: NodeId<GraphOutput>,
+    base_mix: BaseMix,
+    buffer: Vec<i16>,
+}
+
+impl Node for GraphInput {
+    fn update(&mut self, _: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+        let avail = self.ring.len();
+        copy_out_ring(avail, &mut self.buffer, &mut self.ring, outputs);
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("graph_input")
+    }
+
+    fn flush(&mut self) {
+        self.ring.clear();
+    }
+}
+
+impl Node for GraphOutput {
+    fn update(&mut self, inputs: &mut [RingBuffer], _: &mut [RingBuffer]) {
+        self.base_mix.mix_inputs_ring(inputs, &mut self.ring);
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("graph_output")
+    }
+
+    fn flush(&mut self) {
+        self.ring.clear();
+    }
+}
+
+impl SubGraph {
+    pub fn new() -> SubGraph {
+        let mut graph = Graph::new();
+        let input_id = graph.connect(Box::new(GraphInput {
+            ring: RingBuffer::new(),
+            buffer: Vec::new(),
+        }), Default::default());
+        let output_id = graph.connect(Box::new(GraphOutput {
+            base_mix: BaseMix::new(),
+            ring: RingBuffer::new(),
+        }), Default::default());
+        SubGraph {
+            graph: graph,
+            input_id: input_id,
+            output_id: output_id,
+            base_mix: BaseMix::new(),
+            buffer: Vec::new(),
+        }
+    }
+
+    // Build a chain of nodes from the sub graph's input to its output.
+    pub fn chain(nodes: Vec<Box<Node>>) -> SubGraph {
+        let mut sub_graph = SubGraph::new();
+        let mut last_id = sub_graph.input_id();
+        for node in nodes.into_iter() {
+            last_id = sub_graph.graph.connect(node, GraphNodeParams::default().from(last_id)).id();
+        }
+        let output_id = sub_graph.output_id();
+        sub_graph.graph.add_edge(last_id, output_id).unwrap();
+        sub_graph
+    }
+
+    pub fn input_id(&self) -> usize {
+        self.input_id.id()
+    }
+
+    pub fn output_id(&self) -> usize {
+        self.output_id.id()
+    }
+
+    pub fn graph(&self) -> &Graph {
+        &self.graph
+    }
+
+    pub fn graph_mut(&mut self) -> &mut Graph {
+        &mut self.graph
+    }
+}
+
+impl Node for SubGraph {
+    fn update(&mut self, inputs: &mut [RingBuffer], outputs: &mut [RingBuffer]) {
+        {
+            let input = self.graph.get_mut(self.input_id).unwrap();
+            self.base_mix.mix_inputs_ring(inputs, &mut input.ring);
+        }
+        self.graph.update();
+        let output = self.graph.get_mut(self.output_id).unwrap();
+        let avail = output.ring.len();
+        copy_out_ring(avail, &mut self.buffer, &mut output.ring, outputs);
+    }
+
+    fn info(&self) -> NodeInfo {
+        NodeInfo::new("sub_graph")
+    }
+
+    // The inner graph's nodes are reset and flushed with it.
+    fn reset(&mut self) {
+        self.graph.restart();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SubGraph;
+    use super::super::{Node, RingBuffer, BaseMix, Callback, Capture, Graph, GraphNodeParams};
+
+    fn double() -> Box<Node> {
+        Box::new(Callback::new(Box::new(|input, output| {
+            let avail = input.len();
+            let slice = input.read_slice(avail);
+            for (i, o) in slice.iter().zip(output.write_slice(avail).iter_mut()) {
+                *o = *i * 2;
+            }
+        })))
+    }
+
+    #[test]
+    fn it_updates_as_a_node() {
+        let mut a = SubGraph::chain(vec!(double(), double()));
+        let v1 = (0..48).map(|x| x as i16).collect::<Vec<i16>>();
+        let mut inputs = vec!(RingBuffer::new());
+        inputs[0].write_from(v1.len(), &v1);
+        let mut outputs = vec!(RingBuffer::new());
+        {
+            let n = &mut a as &mut Node;
+            n.update(&mut inputs, &mut outputs);
+        }
+        let mut o1 = Vec::<i16>::new();
+        let avail = outputs[0].len();
+        outputs[0].read_into(avail, &mut o1);
+        assert_eq!(avail, 48);
+        assert_eq!(o1[3], 12);
+    }
+
+    #[test]
+    fn it_connects_in_a_graph() {
+        let mut g = Graph::new();
+        let mix_id = g.connect(Box::new(BaseMix::new()), Default::default());
+        let chain_id = g.connect(Box::new(SubGraph::chain(vec!(double()))), GraphNodeParams::default().to(mix_id));
+        g.connect(Box::new(Capture::new(Box::new(|output| {
+            output.write_from(48, &mut (0i16..48i16).collect());
+        }))), GraphNodeParams::default().to(chain_id));
+        g.update();
+        {
+            let mix = g.get(mix_id).unwrap();
+            assert_eq!(mix.accum.len(), 48);
+            assert_eq!(mix.accum[3], 6);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::io::{self, Write};
+
+fn write_u16<W: Write>(writer: &mut W, value: u16) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8])
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> io::Result<()> {
+    writer.write_all(&[value as u8, (value >> 8) as u8, (value >> 16) as u8, (value >> 24) as u8])
+}
+
+// Write interleaved 16 bit samples as a PCM wav file.
+pub fn write_wav<W: Write>(writer: &mut W, rate: u32, channels: u16, samples: &[i16]) -> io::Result<()> {
+    let data_len = samples.len() as u32 * 2;
+    try!(writer.write_all(b"RIFF"));
+    try!(write_u32(writer, 36 + data_len));
+    try!(writer.write_all(b"WAVE"));
+
+    try!(writer.write_all(b"fmt "));
+    try!(write_u32(writer, 16));
+    // PCM
+    try!(write_u16(writer, 1));
+    try!(write_u16(writer, channels));
+    try!(write_u32(writer, rate));
+    // bytes per second
+    try!(write_u32(writer, rate * channels as u32 * 2));
+    // bytes per frame
+    try!(write_u16(writer, channels * 2));
+    // bits per sample
+    try!(write_u16(writer, 16));
+
+    try!(writer.write_all(b"data"));
+    try!(write_u32(writer, data_len));
+    for &sample in samples.iter() {
+        try!(write_u16(writer, sample as u16));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::write_wav;
+
+    #[test]
+    fn it_writes_a_wav_header() {
+        let mut bytes = Vec::new();
+        write_wav(&mut bytes, 48000, 2, &[1, -1]).unwrap();
+        assert_eq!(bytes.len(), 44 + 4);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[4..8], &[40, 0, 0, 0]);
+        assert_eq!(&bytes[24..28], &[0x80, 0xbb, 0, 0]);
+        assert_eq!(&bytes[40..48], &[4, 0, 0, 0, 1, 0, 0xff, 0xff]);
+    }
+}
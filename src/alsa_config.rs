@@ -0,0 +1,124 @@
+use std::collections::btree_map::BTreeMap;
+
+use rustc_serialize::json::Json;
+
+use graph_utils::Node;
+use graph_nodes::{GraphLoader, NodeConfig, ConfigError};
+
+use alsa_graph::*;
+
+fn find_u64(config: &NodeConfig, json: &Json, field: &'static str) -> Result<Option<u64>, ConfigError> {
+    match json.find(field) {
+        Some(value) => match value.as_u64() {
+            Some(value) => Ok(Some(value)),
+            None => Err(ConfigError::InvalidField(config.name.clone(), field)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn find_str<'a>(config: &'a NodeConfig, field: &'static str) -> Result<Option<&'a str>, ConfigError> {
+    match config.get(field) {
+        Some(value) => match value.as_string() {
+            Some(value) => Ok(Some(value)),
+            None => Err(ConfigError::InvalidField(config.name.clone(), field)),
+        },
+        None => Ok(None),
+    }
+}
+
+fn hw_params_from_config(config: &NodeConfig) -> Result<AlsaHwParams, ConfigError> {
+    let mut hw_params = AlsaHwParams::default();
+    if let Some(json) = config.get("hw_params") {
+        if let Some(channels) = try!(find_u64(config, json, "channels")) {
+            hw_params.channels = channels as u32;
+        }
+        if let Some(rate) = try!(find_u64(config, json, "rate")) {
+            hw_params.rate = rate as u32;
+        }
+        if let Some(periods) = try!(find_u64(config, json, "periods")) {
+            hw_params.periods = periods as u32;
+        }
+        if let Some(period_size) = try!(find_u64(config, json, "period_size")) {
+            hw_params.period_size = period_size as i32;
+        }
+    }
+    Ok(hw_params)
+}
+
+fn hctl_from_config(config: &NodeConfig) -> Result<BTreeMap<String, Vec<(u32, HCtlValue)>>, ConfigError> {
+    let mut hctl = BTreeMap::new();
+    let controls = match config.get("hctl") {
+        Some(controls) => match controls.as_array() {
+            Some(controls) => controls,
+            None => return Err(ConfigError::InvalidField(config.name.clone(), "hctl")),
+        },
+        None => return Ok(hctl),
+    };
+    for control in controls.iter() {
+        let name = match control.find("name").and_then(|name| name.as_string()) {
+            Some(name) => String::from(name),
+            None => return Err(ConfigError::InvalidField(config.name.clone(), "hctl")),
+        };
+        let index = try!(find_u64(config, control, "index")).unwrap_or(0) as u32;
+        let value = if let Some(b) = control.find("boolean").and_then(|b| b.as_boolean()) {
+            HCtlValue::Boolean(b)
+        }
+        else if let Some(i) = control.find("integer").and_then(|i| i.as_i64()) {
+            HCtlValue::Integer(i as i32)
+        }
+        else if let Some(e) = control.find("enumerated").and_then(|e| e.as_u64()) {
+            HCtlValue::Enumerated(e as u32)
+        }
+        else if let Some(i) = control.find("integer64").and_then(|i| i.as_i64()) {
+            HCtlValue::Integer64(i)
+        }
+        else {
+            return Err(ConfigError::InvalidField(config.name.clone(), "hctl"));
+        };
+        hctl.entry(name).or_insert_with(Vec::new).push((index, value));
+    }
+    Ok(hctl)
+}
+
+pub fn alsa_card_from_config(config: &NodeConfig) -> Result<AlsaCard, ConfigError> {
+    let mut card = AlsaCard {
+        debug_name: String::from(try!(find_str(config, "debug_name")).unwrap_or(config.name.as_str())),
+        hw_params: try!(hw_params_from_config(config)),
+        hctl: try!(hctl_from_config(config)),
+        ..Default::default()
+    };
+    if let Some(pcm_hint) = try!(find_str(config, "pcm_hint")) {
+        card.pcm_hint = String::from(pcm_hint);
+    }
+    if let Some(port) = try!(find_str(config, "usb_port")) {
+        card.alsa_hint = AlsaUsbPort(String::from(port));
+    }
+    else if let Some(name) = try!(find_str(config, "alsa_name")) {
+        card.alsa_hint = AlsaName(String::from(name));
+    }
+    else if let Some(longname) = try!(find_str(config, "long_name")) {
+        card.alsa_hint = AlsaLongName(String::from(longname));
+    }
+    if config.get("sw_params_ms").is_some() {
+        card.sw_params = AlsaSwParams::new_ms(try!(config.get_u64("sw_params_ms")) as i32);
+    }
+    Ok(card)
+}
+
+// Teach a loader to build "alsa_playback" and "alsa_capture" nodes.
+pub fn add_alsa_kinds(loader: &mut GraphLoader, alsa_factory_view: AlsaFactoryView) {
+    let playback_view = alsa_factory_view.clone();
+    loader.add_kind("alsa_playback", Box::new(move |config: &NodeConfig| {
+        let card = try!(alsa_card_from_config(config));
+        let node: Box<Node> = playback_view.playback(card);
+        Ok(node)
+    }));
+
+    let capture_view = alsa_factory_view;
+    loader.add_kind("alsa_capture", Box::new(move |config: &NodeConfig| {
+        let card = try!(alsa_card_from_config(config));
+        let node: Box<Node> = capture_view.capture(card);
+        Ok(node)
+    }));
+}
@@ -8,7 +8,7 @@ use std::ffi::CString;
 
 use std::collections::btree_map::BTreeMap;
 
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use std::slice;
 
@@ -25,17 +25,17 @@ use alsa::ctl::{Ctl, ElemType, ElemValue};
 use alsa::hctl::{HCtl, Elem};
 use alsa::pcm::{PCM, HwParams, SwParams, Format, Access, State};
 
-use graph_utils::{Capture, Playback, RingBuffer};
+use graph_utils::{Capture, Clock, Frame, Playback, RingBuffer, StreamFormat, TimeMark};
 use graph_nodes::*;
 
 pub enum AlsaCardHint {
     AlsaNone,
     // UsbPort("usb-101c0000.ehci-1.2")
-    AlsaUsbPort(&'static str),
+    AlsaUsbPort(String),
     // Name("USB Sound Device")
-    AlsaName(&'static str),
+    AlsaName(String),
     // LongName("USB Sound Device at usb-101c0000.ehci-1.2, full speed")
-    AlsaLongName(&'static str),
+    AlsaLongName(String),
 }
 
 pub use self::AlsaCardHint::*;
@@ -44,15 +44,15 @@ impl AlsaCardHint {
     fn match_longname(&self, longname: &String) -> bool {
         match self {
             &AlsaNone => false,
-            &AlsaUsbPort(port) => {
-                if let Some(index) = longname.find(port) {
+            &AlsaUsbPort(ref port) => {
+                if let Some(index) = longname.find(port.as_str()) {
                     let s = &longname[(index + port.len())..(index + port.len() + 1)];
                     s == ","
                 }
                 else {false}
             },
-            &AlsaName(n) => longname.starts_with(n),
-            &AlsaLongName(ln) => longname == ln,
+            &AlsaName(ref n) => longname.starts_with(n.as_str()),
+            &AlsaLongName(ref ln) => longname == ln,
         }
     }
 }
@@ -80,6 +80,10 @@ impl Default for AlsaHwParams {
 }
 
 impl AlsaHwParams {
+    pub fn stream_format(&self) -> StreamFormat {
+        StreamFormat::new(self.rate as usize, self.channels as usize)
+    }
+
     pub fn new_32ms() -> AlsaHwParams {
         AlsaHwParams {
             periods: 32,
@@ -257,20 +261,20 @@ pub enum HCtlValue {
 }
 
 pub struct AlsaCard {
-    pub debug_name: &'static str,
-    pub pcm_hint: &'static str,
+    pub debug_name: String,
+    pub pcm_hint: String,
     pub alsa_hint: AlsaCardHint,
     pub pcm_device: usize,
     pub hw_params: AlsaHwParams,
     pub sw_params: AlsaSwParams,
-    pub hctl: BTreeMap<&'static str, Vec<(u32, HCtlValue)>>,
+    pub hctl: BTreeMap<String, Vec<(u32, HCtlValue)>>,
 }
 
 impl Default for AlsaCard {
     fn default() -> AlsaCard {
         AlsaCard {
-            debug_name: "default",
-            pcm_hint: "default",
+            debug_name: String::from("default"),
+            pcm_hint: String::from("default"),
             alsa_hint: AlsaNone,
             pcm_device: 0,
             hw_params: Default::default(),
@@ -538,12 +542,14 @@ impl<'a> Iterator for AlsaCardIter<'a> {
 pub struct AlsaFactory {
     alsa_card_list_inner: AlsaCardListInner,
     activation_controller: ActivationController,
+    clock: Clock,
 }
 
 #[derive(Clone)]
 pub struct AlsaFactoryView {
     alsa_card_list: AlsaCardList,
     activation_controller: ActivationController,
+    clock: Clock,
 }
 
 // struct AlsaFeatures {
@@ -553,9 +559,16 @@ pub struct AlsaFactoryView {
 
 impl AlsaFactory {
     pub fn new(activation_controller: ActivationController) -> AlsaFactory {
+        AlsaFactory::with_clock(activation_controller, Clock::system())
+    }
+
+    // Devices cool off after errors on the given clock instead of the
+    // system's.
+    pub fn with_clock(activation_controller: ActivationController, clock: Clock) -> AlsaFactory {
         AlsaFactory {
             alsa_card_list_inner: AlsaCardListInner::new(),
             activation_controller: activation_controller,
+            clock: clock,
         }
     }
 
@@ -567,30 +580,42 @@ impl AlsaFactory {
         AlsaFactoryView {
             alsa_card_list: self.alsa_card_list_inner.view(),
             activation_controller: self.activation_controller.clone(),
+            clock: self.clock.clone(),
         }
     }
 }
 
 impl AlsaFactoryView {
     pub fn playback(&self, card: AlsaCard) -> Box<Playback> {
+        let format = card.hw_params.stream_format();
+        let name = card.debug_name.clone();
+        // A full device buffer is queued ahead of what is playing.
+        let latency = Duration::from_millis(card.hw_params.period_size as u64 * card.hw_params.periods as u64 * 1000 / card.hw_params.rate as u64);
         let alsa_card_list = self.alsa_card_list.clone();
         let activation_controller_clone = self.activation_controller.clone();
         let mut activation_guard = None;
+        let clock = self.clock.clone();
 
         let mut maybe_pcm_io = None;
         let mut buffer = Vec::new();
         let pcm_period = card.hw_params.period_size as usize;
         let pcm_max = pcm_period * card.hw_params.periods as usize;
+        let num_channels = card.hw_params.channels as usize;
+        let mut frames = Frame::new(num_channels);
+        let rate = card.hw_params.rate as usize;
+
+        let mut marks = Vec::new();
+        let mut latency_report = Instant::now();
 
         let mut cooloff = false;
-        let mut cooloff_start = Instant::now();
+        let mut cooloff_start = clock.now();
 
         let mut paused = false;
 
         Box::new(Playback::new(Box::new(move |input| {
             if maybe_pcm_io.is_none() {
                 if cooloff {
-                    if Instant::now().duration_since(cooloff_start).as_secs() > 4 {
+                    if clock.now().duration_since(cooloff_start).as_secs() > 4 {
                         cooloff = false;
                     }
                     else {
@@ -643,7 +668,7 @@ impl AlsaFactoryView {
                                 activation_guard = None;
                                 println!("failed to activate {:?} playback", card.debug_name);
                                 cooloff = true;
-                                cooloff_start = Instant::now();
+                                cooloff_start = clock.now();
                             }
                             card.sw_params.set_params(&pcm);
 
@@ -660,13 +685,13 @@ impl AlsaFactoryView {
                             println!("failed to activate {:?} playback", card.debug_name);
 
                             cooloff = true;
-                            cooloff_start = Instant::now();
+                            cooloff_start = clock.now();
                         }
                     }
                 }
                 else {
                     cooloff = true;
-                    cooloff_start = Instant::now();
+                    cooloff_start = clock.now();
                 }
             }
             let mut unset = false;
@@ -707,30 +732,52 @@ impl AlsaFactoryView {
             if unset {
                 maybe_pcm_io = None;
                 cooloff = true;
-                cooloff_start = Instant::now();
+                cooloff_start = clock.now();
             }
             if let Some(ref mut pcm) = maybe_pcm_io {
                 if paused {
                     return;
                 }
-                let mut buffer_avail = input.len() / 2;
+                let mut buffer_avail = input.frames(num_channels);
                 if let Ok(pcm_avail) = pcm.avail().map(|x| x as usize) {
                     if pcm_max - pcm_avail < pcm_period {
+                        // The graph fell behind and the device is about to
+                        // run dry, so it plays silence.
+                        if pcm_avail > buffer_avail {
+                            input.underruns += 1;
+                        }
                         buffer_avail += pcm_max - pcm_avail;
-                        for _ in buffer.len()..((pcm_max - pcm_avail) * 2) {
+                        for _ in buffer.len()..((pcm_max - pcm_avail) * num_channels) {
                             buffer.push(0);
                         }
-                        for i in 0..((pcm_max - pcm_avail) * 2) {
+                        for i in 0..((pcm_max - pcm_avail) * num_channels) {
                             buffer[i] = 0;
                         }
-                        input.write_from((pcm_max - pcm_avail) * 2, &buffer);
+                        input.write_from((pcm_max - pcm_avail) * num_channels, &buffer);
                     }
                     let avail = min(pcm_avail, buffer_avail);
 
                     if avail > 0 {
-                        input.read_into(avail * 2, &mut buffer);
+                        let now = Instant::now();
+                        if now.duration_since(latency_report).as_secs() >= 10 {
+                            marks.clear();
+                            input.peek_marks(avail * num_channels, &mut marks);
+                            // Report the newest mark from each capture.
+                            for (i, &(offset, ref mark)) in marks.iter().enumerate() {
+                                if marks[(i + 1)..].iter().any(|&(_, ref later)| later.source == mark.source) {
+                                    continue;
+                                }
+                                // frames the device plays before the marked one
+                                let queued = pcm_max - pcm_avail + offset / num_channels;
+                                let latency = mark.latency(now);
+                                let ms = latency.as_secs() * 1000 + (latency.subsec_nanos() / 1000000) as u64 + (queued * 1000 / rate) as u64;
+                                println!("latency {:?} capture to {:?} playback {}ms", mark.source, card.debug_name, ms);
+                                latency_report = now;
+                            }
+                        }
+                        frames.read_from(input, avail);
                         if let Ok(io) = pcm.io_i16() {
-                            if let Err(_) = io.writei(&buffer[..(avail * 2)]) {
+                            if let Err(_) = io.writei(frames.samples()) {
                                 println!("error writing {:?} playback", card.debug_name);
                                 // unset =  true;
                             }
@@ -754,26 +801,31 @@ impl AlsaFactoryView {
             if unset {
                 maybe_pcm_io = None;
                 cooloff = true;
-                cooloff_start = Instant::now();
+                cooloff_start = clock.now();
             }
-        })))
+        })).with_format(format).with_name(&name).with_latency(latency))
     }
 
     pub fn capture(&self, card: AlsaCard) -> Box<Capture> {
+        let format = card.hw_params.stream_format();
+        let name = card.debug_name.clone();
+        // A period is captured before any of it can be read.
+        let latency = Duration::from_millis(card.hw_params.period_size as u64 * 1000 / card.hw_params.rate as u64);
         let alsa_card_list = self.alsa_card_list.clone();
         let activation_controller_clone = self.activation_controller.clone();
         let mut activation_guard = None;
+        let clock = self.clock.clone();
 
         let mut active_capture = None;
 
         let mut cooloff = false;
-        let mut cooloff_start = Instant::now();
+        let mut cooloff_start = clock.now();
 
         Box::new(Capture::new(Box::new(move |output| {
             output.active = false;
             if active_capture.is_none() {
                 if cooloff {
-                    if Instant::now().duration_since(cooloff_start).as_secs() > 4 {
+                    if clock.now().duration_since(cooloff_start).as_secs() > 4 {
                         cooloff = false;
                     }
                     else {
@@ -827,7 +879,7 @@ impl AlsaFactoryView {
                                 activation_guard = None;
                                 println!("failed to activate {:?} capture", card.debug_name);
                                 cooloff = true;
-                                cooloff_start = Instant::now();
+                                cooloff_start = clock.now();
                             }
                             card.sw_params.set_params(&pcm);
 
@@ -842,7 +894,7 @@ impl AlsaFactoryView {
                             activation_guard = None;
                             println!("failed to activate {:?} capture", card.debug_name);
                             cooloff = true;
-                            cooloff_start = Instant::now();
+                            cooloff_start = clock.now();
                             None
                         }
                     }
@@ -852,7 +904,7 @@ impl AlsaFactoryView {
                 }
                 else {
                     cooloff = true;
-                    cooloff_start = Instant::now();
+                    cooloff_start = clock.now();
                     None
                 };
 
@@ -861,7 +913,8 @@ impl AlsaFactoryView {
                     let mut reading = false;
 
                     output.clear();
-                    let debug_name = card.debug_name.clone();
+                    // shared by the marks on what it captures
+                    let debug_name: Arc<str> = card.debug_name.as_str().into();
                     let num_channels = card.hw_params.channels as usize;
                     let format = card.hw_params.format;
                     let start_threshold = card.sw_params.start_threshold as usize;
@@ -1011,7 +1064,12 @@ impl AlsaFactoryView {
                             None
                         };
                         if let Some(read) = maybe_read {
+                            let position = output.written();
                             output.write_from(read * num_channels, &buffer);
+                            // The last frame read was captured about now.
+                            if read > 0 {
+                                output.mark(position + ((read - 1) * num_channels) as u64, TimeMark::now(debug_name.clone()));
+                            }
                         }
                         unset
                     }))
@@ -1028,9 +1086,9 @@ impl AlsaFactoryView {
                 if unset {
                     active_capture = None;
                     cooloff = true;
-                    cooloff_start = Instant::now();
+                    cooloff_start = clock.now();
                 }
             }
-        })))
+        })).with_format(format).with_name(&name).with_latency(latency))
     }
 }
@@ -202,8 +202,8 @@ fn main() {
     };
 
     let toslink_out_id = graph.connect(alsa_playback(AlsaCard {
-        debug_name: "toslink",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.2, full speed"),
+        debug_name: String::from("toslink"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.2, full speed")),
         hw_params: AlsaHwParams::new_32ms(),
         sw_params: AlsaSwParams::new_16ms(),
         ..Default::default()
@@ -211,207 +211,141 @@ fn main() {
 
     let toslink_switch_gate = SwitchState::new();
 
-    let toslink_1_switch_id = graph.connect(switch_gated(toslink_switch_gate.clone(), 1), GraphNodeParams {
-        to: vec!(toslink_out_id),
-        ..Default::default()
-    });
+    let toslink_1_switch_id = graph.connect(switch_gated(toslink_switch_gate.clone(), 1), GraphNodeParams::default().to(toslink_out_id));
 
     let toslink_in_id = graph.connect(alsa_capture(AlsaCard {
-        debug_name: "PS4 toslink",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.2, full speed"),
+        debug_name: String::from("PS4 toslink"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.2, full speed")),
         hw_params: AlsaHwParams::new_32ms(),
         sw_params: AlsaSwParams::new_2ms(),
         hctl: vec![
-            ("PCM Capture Source", vec!((0, HCtlValue::Enumerated(2)))),
+            (String::from("PCM Capture Source"), vec!((0, HCtlValue::Enumerated(2)))),
         ].into_iter().collect(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(toslink_1_switch_id),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(toslink_1_switch_id));
 
-    let toslink_2_switch_id = graph.connect(switch_gated(toslink_switch_gate.clone(), 2), GraphNodeParams {
-        to: vec!(toslink_out_id),
-        ..Default::default()
-    });
+    let toslink_2_switch_id = graph.connect(switch_gated(toslink_switch_gate.clone(), 2), GraphNodeParams::default().to(toslink_out_id));
 
     let toslink_2_in_id = graph.connect(alsa_capture(AlsaCard {
-        debug_name: "PC toslink",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.1.2.1, full speed"),
+        debug_name: String::from("PC toslink"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.1.2.1, full speed")),
         hw_params: AlsaHwParams::new_32ms(),
         sw_params: AlsaSwParams::new_2ms(),
         hctl: vec![
-            ("PCM Capture Source", vec!((0, HCtlValue::Enumerated(2)))),
+            (String::from("PCM Capture Source"), vec!((0, HCtlValue::Enumerated(2)))),
         ].into_iter().collect(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(toslink_2_switch_id),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(toslink_2_switch_id));
 
     let device_out_id = graph.connect(alsa_playback(AlsaCard {
-        debug_name: "PS4 Chat",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.1.1, full speed"),
+        debug_name: String::from("PS4 Chat"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.1.1, full speed")),
         hw_params: AlsaHwParams::new_44100hz_64ms(),
         sw_params: AlsaSwParams::new_32ms(),
         ..Default::default()
     }), GraphNodeParams { ..Default::default() });
 
     let device_2_out_id = graph.connect(alsa_playback(AlsaCard {
-        debug_name: "PC Chat",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.1.4.3.1, full speed"),
+        debug_name: String::from("PC Chat"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.1.4.3.1, full speed")),
         hw_params: AlsaHwParams::new_44100hz_64ms(),
         sw_params: AlsaSwParams::new_32ms(),
         ..Default::default()
     }), GraphNodeParams { ..Default::default() });
 
-    let device_48_to_44_id = graph.connect(r48_to_r44(), GraphNodeParams {
-        to: vec!(device_out_id, device_2_out_id),
-        ..Default::default()
-    });
+    let device_48_to_44_id = graph.connect(r48_to_r44(), GraphNodeParams::default().to(device_out_id).to(device_2_out_id));
 
-    let device_mix_id = graph.connect(Box::new(BaseMix::new()), GraphNodeParams {
-        to: vec!(device_48_to_44_id),
-        ..Default::default()
-    });
+    let device_mix_id = graph.connect(Box::new(BaseMix::new()), GraphNodeParams::default().to(device_48_to_44_id));
 
     let transmitter_out_id = graph.connect(alsa_playback(AlsaCard {
-        debug_name: "transmitter",
-        alsa_hint: AlsaLongName("Astro Gaming Inc. ASTRO Wireless Transmitter at usb-101c0000.ehci-1.1.4.1, full"),
+        debug_name: String::from("transmitter"),
+        alsa_hint: AlsaLongName(String::from("Astro Gaming Inc. ASTRO Wireless Transmitter at usb-101c0000.ehci-1.1.4.1, full")),
         hw_params: AlsaHwParams::new_32ms(),
         sw_params: AlsaSwParams::new_4ms(),
         ..Default::default()
     }), GraphNodeParams { ..Default::default() });
 
-    let transmitter_lean_id = graph.connect(lean(768), GraphNodeParams {
-        to: vec!(transmitter_out_id),
-        ..Default::default()
-    });
+    let transmitter_lean_id = graph.connect(lean(768), GraphNodeParams::default().to(transmitter_out_id));
 
     let office_out_id = graph.connect(alsa_playback(AlsaCard {
-        debug_name: "office",
-        alsa_hint: AlsaLongName("C-Media Electronics Inc. USB Audio Device at usb-101c0000.ehci-1.1, full speed"),
+        debug_name: String::from("office"),
+        alsa_hint: AlsaLongName(String::from("C-Media Electronics Inc. USB Audio Device at usb-101c0000.ehci-1.1, full speed")),
         hw_params: AlsaHwParams::new_44100hz_32ms(),
         sw_params: AlsaSwParams::new_4ms(),
         ..Default::default()
     }), Default::default());
 
-    let office_r48_id = graph.connect(r48_to_r44(), GraphNodeParams {
-        to: vec!(office_out_id),
-        ..Default::default()
-    });
+    let office_r48_id = graph.connect(r48_to_r44(), GraphNodeParams::default().to(office_out_id));
 
     // let transmitter_mix_id = graph.connect(Box::new(BaseMix::new()), GraphNodeParams {
     //     to: vec!(transmitter_lean_id, office_r48_id),
     //     ..Default::default()
     // });
 
-    let transmitter_mix_id = graph.connect(meter(tessel), GraphNodeParams {
-        to: vec!(transmitter_lean_id, office_r48_id),
-        ..Default::default()
-    });
+    let transmitter_mix_id = graph.connect(meter(tessel), GraphNodeParams::default().to(transmitter_lean_id).to(office_r48_id));
 
     let device_duck_state = DuckState::new();
 
-    let device_duck_in_id = graph.connect(duck(1000, device_duck_state.clone()), GraphNodeParams {
-        to: vec!(transmitter_mix_id),
-        ..Default::default()
-    });
+    let device_duck_in_id = graph.connect(duck(1000, device_duck_state.clone()), GraphNodeParams::default().to(transmitter_mix_id));
 
-    let device_in_44_to_48 = graph.connect(r44_to_r48(), GraphNodeParams {
-        to: vec!(device_duck_in_id),
-        ..Default::default()
-    });
+    let device_in_44_to_48 = graph.connect(r44_to_r48(), GraphNodeParams::default().to(device_duck_in_id));
 
     let device_in_id = graph.connect(alsa_capture(AlsaCard {
-        debug_name: "PS4 Chat",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.1.1, full speed"),
+        debug_name: String::from("PS4 Chat"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.1.1, full speed")),
         hw_params: AlsaHwParams::new_44100hz_32ms(),
         sw_params: AlsaSwParams::new_16ms(),
         hctl: vec![
-            ("PCM Capture Source", vec!((0, HCtlValue::Enumerated(0)))),
+            (String::from("PCM Capture Source"), vec!((0, HCtlValue::Enumerated(0)))),
         ].into_iter().collect(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(device_in_44_to_48),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(device_in_44_to_48));
 
-    let device_2_in_44_to_48 = graph.connect(r44_to_r48(), GraphNodeParams {
-        to: vec!(device_duck_in_id),
-        ..Default::default()
-    });
+    let device_2_in_44_to_48 = graph.connect(r44_to_r48(), GraphNodeParams::default().to(device_duck_in_id));
 
     let device_2_in_id = graph.connect(alsa_capture(AlsaCard {
-        debug_name: "PC Chat",
-        alsa_hint: AlsaLongName("USB Sound Device at usb-101c0000.ehci-1.1.4.3.1, full speed"),
-        hw_params: AlsaHwParams::new_32ms(),
+        debug_name: String::from("PC Chat"),
+        alsa_hint: AlsaLongName(String::from("USB Sound Device at usb-101c0000.ehci-1.1.4.3.1, full speed")),
+        hw_params: AlsaHwParams::new_44100hz_32ms(),
         sw_params: AlsaSwParams::new_16ms(),
         hctl: vec![
-            ("PCM Capture Source", vec!((0, HCtlValue::Enumerated(1)))),
+            (String::from("PCM Capture Source"), vec!((0, HCtlValue::Enumerated(1)))),
         ].into_iter().collect(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(device_2_in_44_to_48),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(device_2_in_44_to_48));
 
     let mic_duck_state = DuckState::new();
 
-    let mic_in_duck = graph.connect(duck(5500, device_duck_state.clone()), GraphNodeParams {
-        to: vec!(transmitter_mix_id, device_mix_id),
-        ..Default::default()
-    });
+    let mic_in_duck = graph.connect(duck(5500, device_duck_state.clone()), GraphNodeParams::default().to(transmitter_mix_id).to(device_mix_id));
 
     let streammic_in_id = graph.connect(alsa_capture(AlsaCard {
-        debug_name: "Stream Mic",
-        alsa_hint: AlsaLongName("Turtle Beach Turtle Beach Stream Mic (Mic On at usb-101c0000.ehci-1.1.4.4.1, fu"),
+        debug_name: String::from("Stream Mic"),
+        alsa_hint: AlsaLongName(String::from("Turtle Beach Turtle Beach Stream Mic (Mic On at usb-101c0000.ehci-1.1.4.4.1, fu")),
         hw_params: AlsaHwParams::new_32ms(),
         sw_params: AlsaSwParams::new_2ms(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(mic_in_duck),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(mic_in_duck));
 
-    let transmitter_stereo_id = graph.connect(mono_to_stereo(), GraphNodeParams {
-        to: vec!(mic_in_duck),
-        ..Default::default()
-    });
+    let transmitter_stereo_id = graph.connect(mono_to_stereo(), GraphNodeParams::default().to(mic_in_duck));
 
     let transmitter_in_id = graph.connect(alsa_capture(AlsaCard {
-        debug_name: "transmitter",
-        alsa_hint: AlsaLongName("Astro Gaming Inc. ASTRO Wireless Transmitter at usb-101c0000.ehci-1.1.4.1, full"),
+        debug_name: String::from("transmitter"),
+        alsa_hint: AlsaLongName(String::from("Astro Gaming Inc. ASTRO Wireless Transmitter at usb-101c0000.ehci-1.1.4.1, full")),
         hw_params: AlsaHwParams::new_mono_32ms(),
         sw_params: AlsaSwParams::new_2ms(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(transmitter_stereo_id),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(transmitter_stereo_id));
 
-    let content_duck_id = graph.connect(ducked(vec!(mic_duck_state, device_duck_state), (1, 5)), GraphNodeParams {
-        to: vec!(transmitter_mix_id),
-        ..Default::default()
-    });
+    let content_duck_id = graph.connect(ducked(vec!(mic_duck_state, device_duck_state), (1, 5)), GraphNodeParams::default().to(transmitter_mix_id));
 
     let mut music_buffer = IoNodeBuffer::new("music", activation_controller.clone());
-    let http_music_in_id = graph.connect(music_buffer.capture(), GraphNodeParams {
-        to: vec!(content_duck_id),
-        ..Default::default()
-    });
+    let http_music_in_id = graph.connect(music_buffer.capture().unwrap(), GraphNodeParams::default().to(content_duck_id));
 
     let chrome_device_gate = GateState::new();
-    let chrome_gated_id = graph.connect(gated(chrome_device_gate.clone()), GraphNodeParams {
-        to: vec!(device_mix_id),
-        ..Default::default()
-    });
+    let chrome_gated_id = graph.connect(gated(chrome_device_gate.clone()), GraphNodeParams::default().to(device_mix_id));
 
     let mut chrome_buffer = IoNodeBuffer::new("chrome", activation_controller.clone());
-    let http_chrome_in_id = graph.connect(chrome_buffer.capture(), GraphNodeParams {
-        to: vec!(content_duck_id, chrome_gated_id),
-        ..Default::default()
-    });
+    let http_chrome_in_id = graph.connect(chrome_buffer.capture().unwrap(), GraphNodeParams::default().to(content_duck_id).to(chrome_gated_id));
 
     let music_http = {
         let cb = music_buffer.read_factory().reader();
@@ -593,6 +527,8 @@ format!(r#"
 
     let mut steps = 0;
 
+    graph.set_profiling(true);
+
     let start = Instant::now();
     let mut net_start = false;
     let mut last_hint_run = Instant::now();
@@ -614,6 +550,14 @@ format!(r#"
             let ns = Instant::now().duration_since(now).subsec_nanos();
             if ns > 1000000 {
                 println!("{} ", ns);
+                for id in graph.node_ids() {
+                    if let Some(profile) = graph.profile(id) {
+                        if profile.last_ns > 250000 {
+                            let label = graph.info(id).map_or(String::new(), |info| info.label().to_string());
+                            println!("  node {} {} last {} max {} average {}", id, label, profile.last_ns, profile.max_ns, profile.average_ns());
+                        }
+                    }
+                }
             }
             steps = 0;
         }
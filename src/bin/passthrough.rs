@@ -22,31 +22,25 @@ fn main() {
     let mut alsa_factory = AlsaFactory::new(activation_controller.clone());
 
     let simple_out_id = graph.connect(alsa_factory.view().playback(AlsaCard {
-        debug_name: "simple",
-        alsa_hint: AlsaUsbPort("usb-101c0000.ehci-1.1"),
+        debug_name: String::from("simple"),
+        alsa_hint: AlsaUsbPort(String::from("usb-101c0000.ehci-1.1")),
         hw_params: AlsaHwParams::new_44100hz_8ms(),
         sw_params: AlsaSwParams::new_4ms(),
         ..Default::default()
     }), Default::default());
 
-    let simple_stereo_id = graph.connect(MonoToStereo::new(), GraphNodeParams {
-        to: vec!(simple_out_id),
-        ..Default::default()
-    });
+    let simple_stereo_id = graph.connect(MonoToStereo::new(), GraphNodeParams::default().to(simple_out_id));
 
     graph.connect(alsa_factory.view().capture(AlsaCard {
-        debug_name: "simple",
-        alsa_hint: AlsaUsbPort("usb-101c0000.ehci-1.1"),
+        debug_name: String::from("simple"),
+        alsa_hint: AlsaUsbPort(String::from("usb-101c0000.ehci-1.1")),
         hw_params: AlsaHwParams {
             channels: 1,
             ..AlsaHwParams::new_44100hz_8ms()
         },
         sw_params: AlsaSwParams::new_4ms(),
         ..Default::default()
-    }), GraphNodeParams {
-        to: vec!(simple_stereo_id),
-        ..Default::default()
-    });
+    }), GraphNodeParams::default().to(simple_stereo_id));
 
     loop {
         yield_now();
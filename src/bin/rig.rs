@@ -0,0 +1,180 @@
+//! Run a graph described by a json config file
+//!
+//! `rig path/to/rig.json [threads]`
+//!
+//! Saving changes to the config applies them without restarting, keeping
+//! nodes that didn't change, like open alsa devices, running.
+
+// Import the graph_utils library
+extern crate graph_utils;
+// Import the graph_nodes library
+extern crate graph_nodes;
+// Import the tessel_audio_graph library
+extern crate tessel_audio_graph;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::thread::yield_now;
+use std::thread;
+use std::time::{Instant, SystemTime};
+
+use graph_utils::*;
+use graph_nodes::*;
+use tessel_audio_graph::*;
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            println!("usage: rig path/to/rig.json [threads]");
+            return;
+        },
+    };
+
+    let mut config = match File::open(&path) {
+        Ok(mut file) => GraphConfig::from_reader(&mut file).unwrap(),
+        Err(err) => {
+            println!("couldn't open {}: {:?}", path, err);
+            return;
+        },
+    };
+
+    let mut graph = Graph::new();
+    if let Some(threads) = env::args().nth(2) {
+        graph.set_threads(threads.parse().expect("threads should be a number"));
+    }
+
+    let activation_controller = ActivationController::new();
+
+    let mut alsa_factory = AlsaFactory::new(activation_controller.clone());
+
+    let mut loader = GraphLoader::new(activation_controller.clone());
+    add_alsa_kinds(&mut loader, alsa_factory.view());
+
+    let mut handles = loader.load(&config, &mut graph).unwrap();
+
+    let mut listeners = BTreeMap::new();
+    for node_config in config.nodes.iter() {
+        listen(node_config, &handles, &mut listeners);
+    }
+
+    let mut modified = modified_time(&path);
+    let mut last_check = Instant::now();
+
+    loop {
+        yield_now();
+        graph.update();
+
+        let now = Instant::now();
+
+        alsa_factory.update(now);
+        for io_buffer in handles.io_buffers.values_mut() {
+            io_buffer.update(now);
+        }
+
+        if now.duration_since(last_check).as_secs() >= 1 {
+            last_check = now;
+            let new_modified = modified_time(&path);
+            if new_modified != modified {
+                modified = new_modified;
+                let new_config = match File::open(&path) {
+                    Ok(mut file) => match GraphConfig::from_reader(&mut file) {
+                        Ok(new_config) => new_config,
+                        Err(err) => {
+                            println!("couldn't read {}: {:?}", path, err);
+                            continue;
+                        },
+                    },
+                    Err(err) => {
+                        println!("couldn't open {}: {:?}", path, err);
+                        continue;
+                    },
+                };
+                match loader.reload(&config, &new_config, &mut graph, &mut handles) {
+                    Ok(built) => {
+                        println!("reloaded {}, built {:?}", path, built);
+                        close_unused(&new_config, &listeners);
+                        for node_config in new_config.nodes.iter().filter(|node_config| built.contains(&node_config.name)) {
+                            listen(node_config, &handles, &mut listeners);
+                        }
+                        config = new_config;
+                    },
+                    Err(err) => println!("couldn't reload {}: {:?}", path, err),
+                }
+            }
+        }
+    }
+}
+
+// Stop handing connections on ports config no longer listens on to the io
+// nodes it removed.
+fn close_unused(config: &GraphConfig, listeners: &BTreeMap<u16, ListenerSlot>) {
+    for (&port, slot) in listeners.iter() {
+        if !config.nodes.iter().any(|node_config| tcp_port(node_config) == Some(port)) {
+            *slot.lock().unwrap() = None;
+        }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+type Reader = Arc<Box<Fn(&mut Read) + Send + Sync>>;
+
+// The reader a port's listener hands new connections to, None once no io
+// node listens on the port.
+type ListenerSlot = Arc<Mutex<Option<Reader>>>;
+
+// The port an io node listens on. The loader checked it when it built the
+// node.
+fn tcp_port(node_config: &NodeConfig) -> Option<u16> {
+    if node_config.kind != "io" {
+        return None;
+    }
+    node_config.get_tcp_port().unwrap_or(None)
+}
+
+// io nodes with a tcp_port listen for raw 48khz stereo samples. A port keeps
+// one listener across reloads. A rebuilt io node swaps in its reader for the
+// connections that come after.
+fn listen(node_config: &NodeConfig, handles: &GraphHandles, listeners: &mut BTreeMap<u16, ListenerSlot>) {
+    let port = match tcp_port(node_config) {
+        Some(port) => port,
+        None => return,
+    };
+    let reader: Reader = Arc::new(handles.io_buffers[&node_config.name].read_factory().reader());
+    if let Some(slot) = listeners.get(&port) {
+        *slot.lock().unwrap() = Some(reader);
+        return;
+    }
+    let slot = Arc::new(Mutex::new(Some(reader)));
+    listeners.insert(port, slot.clone());
+
+    thread::spawn(move || {
+        let listener = match TcpListener::bind(("0.0.0.0", port)) {
+            Ok(listener) => listener,
+            Err(err) => {
+                println!("couldn't listen on {}: {:?}", port, err);
+                return;
+            },
+        };
+        for stream in listener.incoming() {
+            match stream {
+                Ok(mut stream) => {
+                    stream.set_nonblocking(true).unwrap();
+                    let cb = slot.lock().unwrap().clone();
+                    // Dropping the stream closes connections no node reads.
+                    if let Some(cb) = cb {
+                        cb(&mut stream);
+                    }
+                },
+                Err(_) => {},
+            }
+        }
+    });
+}
@@ -27,28 +27,19 @@ fn main() {
     let mut alsa_factory = AlsaFactory::new(activation_controller.clone());
 
     let simple_out_id = graph.connect(alsa_factory.view().playback(AlsaCard {
-        debug_name: "simple",
-        alsa_hint: AlsaUsbPort("usb-101c0000.ehci-1.1"),
+        debug_name: String::from("simple"),
+        alsa_hint: AlsaUsbPort(String::from("usb-101c0000.ehci-1.1")),
         hw_params: AlsaHwParams::new_44100hz_64ms(),
         sw_params: AlsaSwParams::new_ms(32),
         ..Default::default()
     }), Default::default());
 
-    let simple_r44_id = graph.connect(Rate::new(48000, 44100), GraphNodeParams {
-        to: vec!(simple_out_id),
-        ..Default::default()
-    });
+    let simple_r44_id = graph.connect(Rate::new(48000, 44100), GraphNodeParams::default().to(simple_out_id));
 
-    let meter_id = graph.connect(LedMeter::new(Tessel::new()), GraphNodeParams {
-        to: vec!(simple_r44_id),
-        ..Default::default()
-    });
+    let meter_id = graph.connect(LedMeter::new(Tessel::new()), GraphNodeParams::default().to(simple_r44_id));
 
     let mut music_buffer = IoNodeBuffer::new("music", activation_controller.clone());
-    graph.connect(music_buffer.capture(), GraphNodeParams {
-        to: vec!(meter_id),
-        ..Default::default()
-    });
+    graph.connect(music_buffer.capture().unwrap(), GraphNodeParams::default().to(meter_id));
 
     let cb = music_buffer.read_factory().reader();
     let music_tcp = move |mut stream: TcpStream| {
@@ -2,9 +2,12 @@ extern crate tessel;
 extern crate alsa;
 extern crate graph_utils;
 extern crate graph_nodes;
+extern crate rustc_serialize;
 
+mod alsa_config;
 mod alsa_graph;
 mod tessel_led_meter;
 
+pub use self::alsa_config::*;
 pub use self::alsa_graph::*;
 pub use self::tessel_led_meter::*;
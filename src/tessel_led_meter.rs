@@ -13,12 +13,22 @@ impl CallbackInner for LedMeter {
     fn get_callback(&mut self) -> &mut Callback {
         &mut self.0
     }
+
+    fn kind(&self) -> &'static str {
+        "led_meter"
+    }
 }
 
 impl LedMeter {
-    pub fn new(mut tessel: Tessel) -> Box<LedMeter> {
+    pub fn new(tessel: Tessel) -> Box<LedMeter> {
+        LedMeter::with_clock(tessel, Clock::system())
+    }
+
+    // Peaks are measured over windows of the given clock. The leds still
+    // render on the system's.
+    pub fn with_clock(mut tessel: Tessel, clock: Clock) -> Box<LedMeter> {
         let mut peak = 0;
-        let mut peak_last = Instant::now();
+        let mut peak_last = clock.now();
         let peak_mutex = Arc::new(Mutex::new(0));
 
         {
@@ -66,7 +76,7 @@ impl LedMeter {
         // buffer and use a Biquad or FFT to target a frequency range and return the peak of that range for
         // the desired window.
         Box::new(LedMeter(Callback::new(Box::new(move |input, output| {
-            let now = Instant::now();
+            let now = clock.now();
             if now.duration_since(peak_last).subsec_nanos() > 8000000 {
                 peak = 0;
                 peak_last = now;